@@ -1,7 +1,7 @@
 extern crate mammut;
 extern crate toml;
 
-use std::{error::Error, fs, io};
+use std::{error::Error, fs, io, time::Duration};
 
 use self::mammut::{
     apps::{AppBuilder, Scopes},
@@ -26,21 +26,21 @@ pub fn get_mastodon_data() -> Result<Mastodon, Box<dyn Error>> {
 pub fn register() -> Result<Mastodon, Box<dyn Error>> {
     let app = AppBuilder {
         client_name: "mammut-examples",
-        redirect_uris: "urn:ietf:wg:oauth:2.0:oob",
         scopes: Scopes::All,
         website: Some("https://github.com/Aaronepower/mammut"),
+        ..AppBuilder::default()
     };
 
     let website = read_line("Please enter your mastodon instance url:")?;
     let mut registration = Registration::new(website.trim());
-    registration.register(app)?;
+    // Listen for the redirect back from the instance on a free local port.
+    let loopback = registration.register_loopback(app, 0)?;
     let url = registration.authorise()?;
 
     println!("Click this link to authorize on Mastodon: {}", url);
-    let input = read_line("Paste the returned authorization code: ")?;
+    println!("Waiting for you to authorize...");
 
-    let code = input.trim();
-    let mastodon = registration.create_access_token(code.to_string())?;
+    let mastodon = registration.complete_loopback(&loopback, Duration::from_secs(300))?;
 
     // Save app data for using on the next run.
     let toml = toml::to_string(&*mastodon)?;
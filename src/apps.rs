@@ -1,4 +1,11 @@
+use std::borrow::Cow;
 use std::fmt;
+use std::iter::FromIterator;
+use std::ops::BitOr;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
 
 /// Builder struct for defining your application.
 /// ```
@@ -26,49 +33,178 @@ pub struct AppBuilder<'a> {
     pub website: Option<&'a str>,
 }
 
-/// Permission scope of the application.
-/// [Details on what each permission provides](//github.com/tootsuite/documentation/blob/master/Using-the-API/OAuth-details.md)
-#[derive(Debug, Clone, Copy, Serialize)]
-pub enum Scopes {
+/// A single OAuth scope, such as `read`, `write:media` or
+/// `admin:read:accounts`.
+/// [Details on what each scope provides](//docs.joinmastodon.org/api/oauth-scopes/)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Scope(Cow<'static, str>);
+
+// What the legacy `follow` scope grants.
+const FOLLOW_SCOPES: &[&str] = &[
+    "read:blocks",
+    "read:follows",
+    "read:mutes",
+    "write:blocks",
+    "write:follows",
+    "write:mutes",
+];
+
+impl Scope {
+    /// Read access to everything the user can see.
+    pub const READ: Scope = Scope(Cow::Borrowed("read"));
+    /// Write access to everything the user can change.
+    pub const WRITE: Scope = Scope(Cow::Borrowed("write"));
+    /// Access to the user's follows, blocks and mutes. Superseded by the
+    /// granular `read:follows`, `write:blocks` and similar scopes.
+    pub const FOLLOW: Scope = Scope(Cow::Borrowed("follow"));
+    /// Access to Web Push subscriptions.
+    pub const PUSH: Scope = Scope(Cow::Borrowed("push"));
+    /// Read access to the moderation API.
+    pub const ADMIN_READ: Scope = Scope(Cow::Borrowed("admin:read"));
+    /// Write access to the moderation API.
+    pub const ADMIN_WRITE: Scope = Scope(Cow::Borrowed("admin:write"));
+
+    /// A scope from its name, such as `"write:media"`.
+    pub fn new<I: Into<Cow<'static, str>>>(name: I) -> Self {
+        Scope(name.into())
+    }
+
+    /// Read access to one kind of resource, e.g. `Scope::read("statuses")`
+    /// is `read:statuses`.
+    pub fn read(resource: &str) -> Self {
+        Scope(format!("read:{}", resource).into())
+    }
+
+    /// Write access to one kind of resource, e.g. `Scope::write("media")`
+    /// is `write:media`.
+    pub fn write(resource: &str) -> Self {
+        Scope(format!("write:{}", resource).into())
+    }
+
+    /// Read access to one kind of resource in the moderation API, e.g.
+    /// `Scope::admin_read("accounts")` is `admin:read:accounts`.
+    pub fn admin_read(resource: &str) -> Self {
+        Scope(format!("admin:read:{}", resource).into())
+    }
+
+    /// Write access to one kind of resource in the moderation API, e.g.
+    /// `Scope::admin_write("reports")` is `admin:write:reports`.
+    pub fn admin_write(resource: &str) -> Self {
+        Scope(format!("admin:write:{}", resource).into())
+    }
+
+    /// The name of the scope, as sent to the instance.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether having this scope grants `other`, either because they're the
+    /// same or because `other` is narrower, e.g. `read` grants
+    /// `read:statuses` and `follow` grants `write:blocks`.
+    pub fn contains(&self, other: &Scope) -> bool {
+        let (this, other) = (self.as_str(), other.as_str());
+
+        this == other
+            || (other.starts_with(this) && other[this.len()..].starts_with(':'))
+            || (this == "follow" && FOLLOW_SCOPES.contains(&other))
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&'static str> for Scope {
+    fn from(name: &'static str) -> Self {
+        Scope::new(name)
+    }
+}
+
+impl From<String> for Scope {
+    fn from(name: String) -> Self {
+        Scope::new(name)
+    }
+}
+
+/// Permission scopes of the application, a set of `Scope`s.
+/// [Details on what each permission provides](//docs.joinmastodon.org/api/oauth-scopes/)
+///
+/// The combinations of `read`, `write` and `follow` are available as
+/// shorthands such as `Scopes::ReadWrite`, and any other set can be built
+/// from `Scope`s or parsed from the space separated form used by OAuth.
+///
+/// ```
+/// use mammut::apps::{Scope, Scopes};
+///
+/// let scopes = Scopes::Read | Scope::write("statuses") | Scope::write("media");
+/// assert_eq!(scopes.to_string(), "read write:media write:statuses");
+///
+/// assert!(scopes.contains(&Scope::read("notifications")));
+/// assert!(!scopes.contains(&Scope::write("follows")));
+///
+/// let parsed: Scopes = "read write:statuses write:media".parse().unwrap();
+/// assert_eq!(parsed, scopes);
+/// assert!(Scopes::ReadWrite.contains_all(&parsed));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Scopes(Cow<'static, [Scope]>);
+
+#[allow(non_upper_case_globals)]
+impl Scopes {
     /// All Permissions, equivalent to `read write follow`
-    #[serde(rename = "read write follow")]
-    All,
+    pub const All: Scopes = Scopes(Cow::Borrowed(&[Scope::FOLLOW, Scope::READ, Scope::WRITE]));
     /// Only permission to add and remove followers.
-    #[serde(rename = "follow")]
-    Follow,
+    pub const Follow: Scopes = Scopes(Cow::Borrowed(&[Scope::FOLLOW]));
     /// Read only permissions.
-    #[serde(rename = "read")]
-    Read,
+    pub const Read: Scopes = Scopes(Cow::Borrowed(&[Scope::READ]));
     /// Read & Follow permissions.
-    #[serde(rename = "read follow")]
-    ReadFollow,
+    pub const ReadFollow: Scopes = Scopes(Cow::Borrowed(&[Scope::FOLLOW, Scope::READ]));
     /// Read & Write permissions.
-    #[serde(rename = "read write")]
-    ReadWrite,
+    pub const ReadWrite: Scopes = Scopes(Cow::Borrowed(&[Scope::READ, Scope::WRITE]));
     /// Write only permissions.
-    #[serde(rename = "write")]
-    Write,
+    pub const Write: Scopes = Scopes(Cow::Borrowed(&[Scope::WRITE]));
     /// Write & Follow permissions.
-    #[serde(rename = "write follow")]
-    WriteFollow,
+    pub const WriteFollow: Scopes = Scopes(Cow::Borrowed(&[Scope::FOLLOW, Scope::WRITE]));
 }
 
-impl fmt::Display for Scopes {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use self::Scopes::*;
-        write!(
-            f,
-            "{}",
-            match *self {
-                All => "read%20write%20follow",
-                Follow => "follow",
-                Read => "read",
-                ReadFollow => "read%20follow",
-                ReadWrite => "read%20write",
-                Write => "write",
-                WriteFollow => "write%20follow",
-            }
-        )
+impl Scopes {
+    /// A set without any scopes.
+    pub fn empty() -> Self {
+        Scopes(Cow::Borrowed(&[]))
+    }
+
+    /// Whether the set has no scopes.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The scopes in the set, in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = &Scope> {
+        self.0.iter()
+    }
+
+    /// Add `scope` to the set.
+    pub fn insert(&mut self, scope: Scope) {
+        if let Err(index) = self.0.binary_search(&scope) {
+            self.0.to_mut().insert(index, scope);
+        }
+    }
+
+    /// The scopes in either this set or `other`.
+    pub fn union(&self, other: &Scopes) -> Scopes {
+        self.iter().chain(other.iter()).cloned().collect()
+    }
+
+    /// Whether any scope in the set grants `scope`, see `Scope::contains`.
+    pub fn contains(&self, scope: &Scope) -> bool {
+        self.iter().any(|granted| granted.contains(scope))
+    }
+
+    /// Whether every scope in `other` is granted by this set.
+    pub fn contains_all(&self, other: &Scopes) -> bool {
+        other.iter().all(|scope| self.contains(scope))
     }
 }
 
@@ -77,3 +213,127 @@ impl Default for Scopes {
         Scopes::Read
     }
 }
+
+impl fmt::Display for Scopes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, scope) in self.iter().enumerate() {
+            if i != 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(scope.as_str())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Scopes {
+    type Err = std::convert::Infallible;
+
+    /// Parses scopes separated by whitespace, e.g. `"read write:media"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.split_whitespace()
+            .map(|scope| Scope::new(scope.to_owned()))
+            .collect())
+    }
+}
+
+impl FromIterator<Scope> for Scopes {
+    fn from_iter<I: IntoIterator<Item = Scope>>(iter: I) -> Self {
+        let mut scopes = iter.into_iter().collect::<Vec<_>>();
+        scopes.sort();
+        scopes.dedup();
+        Scopes(Cow::Owned(scopes))
+    }
+}
+
+impl Extend<Scope> for Scopes {
+    fn extend<I: IntoIterator<Item = Scope>>(&mut self, iter: I) {
+        for scope in iter {
+            self.insert(scope);
+        }
+    }
+}
+
+impl From<Scope> for Scopes {
+    fn from(scope: Scope) -> Self {
+        Scopes(Cow::Owned(vec![scope]))
+    }
+}
+
+impl BitOr for Scopes {
+    type Output = Scopes;
+
+    fn bitor(self, other: Scopes) -> Scopes {
+        self.union(&other)
+    }
+}
+
+impl BitOr<Scope> for Scopes {
+    type Output = Scopes;
+
+    fn bitor(mut self, scope: Scope) -> Scopes {
+        self.insert(scope);
+        self
+    }
+}
+
+impl Serialize for Scopes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Deserialises from a space separated string, as in the `scope` of a token
+/// response. Lists of scopes, as in the `Application` entity, are read by the
+/// fields that hold them.
+impl<'de> Deserialize<'de> for Scopes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Scopes;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("space separated scopes")
+            }
+
+            fn visit_str<E: de::Error>(self, scopes: &str) -> Result<Scopes, E> {
+                scopes.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+/// (De)serialises `Scopes` as a list of scopes rather than a string, for
+/// fields like `ServerMetadata::scopes_supported`, with
+/// `#[serde(with = "crate::apps::scope_list")]`.
+pub(crate) mod scope_list {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::{Scope, Scopes};
+
+    pub(crate) fn serialize<S: Serializer>(
+        scopes: &Scopes,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(scopes.iter().map(Scope::as_str))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Scopes, D::Error> {
+        let scopes = Vec::<String>::deserialize(deserializer)?;
+        Ok(scopes.into_iter().map(Scope::new).collect())
+    }
+
+    // For an optional field, which also needs `#[serde(default)]`.
+    pub(crate) fn deserialize_option<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Scopes>, D::Error> {
+        let scopes = Option::<Vec<String>>::deserialize(deserializer)?;
+        Ok(scopes.map(|scopes| scopes.into_iter().map(Scope::new).collect()))
+    }
+}
@@ -0,0 +1,529 @@
+//! Asynchronous counterparts to `Mastodon`, `Registration` and `Page`.
+//!
+//! Every route returns a `futures` 0.1 `Future` that owns everything it
+//! needs, so it can be spawned onto a tokio runtime directly. This module is
+//! only available with the `async` feature enabled.
+//!
+//! ```
+//! # extern crate futures;
+//! # extern crate mammut;
+//! # extern crate tokio;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let server = mammut::testing::MockServer::start();
+//! # let data = server.data();
+//! use futures::{Future, Stream};
+//! use mammut::r#async::Mastodon;
+//! use tokio::runtime::Runtime;
+//!
+//! let mastodon = Mastodon::from_data(data);
+//! let statuses = mastodon
+//!     .get_home_timeline()
+//!     .and_then(|page| page.items_stream().take(100).collect());
+//!
+//! let statuses = Runtime::new()?.block_on(statuses)?;
+//! # assert_eq!(statuses.len(), 3);
+//! # Ok(())
+//! # }
+//! ```
+
+/// Handling multiple pages of entities asynchronously.
+pub mod page;
+/// Registering your app asynchronously.
+pub mod registration;
+/// Sending requests to any route asynchronously.
+pub mod request_builder;
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::ops;
+use std::sync::Arc;
+use std::time::Instant;
+
+use futures::future::{self, Either, Loop};
+use futures::{Future, IntoFuture};
+use log::trace;
+use reqwest::r#async::Client;
+use reqwest::Method;
+use serde::Deserialize;
+use tokio_timer::Delay;
+
+use crate::apps::Scopes;
+use crate::entities::prelude::*;
+use crate::registration::AccessToken;
+use crate::session::Session;
+use crate::transport::{AsyncTransport, Multipart, Request, Response};
+use crate::{
+    check_status, log_response, redact, Data, Error, MediaBuilder, RateLimit, Result, RetryPolicy,
+    SignupBuilder, StatusBuilder, StatusesRequest,
+};
+
+pub use self::page::Page;
+pub use self::registration::Registration;
+pub use self::request_builder::RequestBuilder;
+
+macro_rules! methods {
+    ($($method:ident,)+) => {
+        $(
+            fn $method<T>(&self, url: String) -> impl Future<Item = T, Error = Error>
+            where
+                T: for<'de> Deserialize<'de>,
+            {
+                self.send(Request::$method(&url)).and_then(|response| response.json())
+            }
+         )+
+    };
+}
+
+/// Your asynchronous mastodon application client, handles all requests to
+/// and from Mastodon.
+#[derive(Clone)]
+pub struct Mastodon {
+    transport: Arc<dyn AsyncTransport>,
+    session: Session,
+    /// Raw data about your mastodon instance.
+    pub data: Data,
+}
+
+impl Mastodon {
+    /// Creates a mastodon instance from the data struct.
+    ///
+    /// # Panics
+    /// If the access token isn't a valid header value. Use `try_from_data`
+    /// to get an error instead.
+    pub fn from_data(data: Data) -> Self {
+        Self::try_from_data(data).expect("access token isn't a valid header value")
+    }
+
+    /// Creates a mastodon instance from the data struct, sending all of its
+    /// requests through `transport`.
+    ///
+    /// # Panics
+    /// If the access token isn't a valid header value. Use
+    /// `try_from_data_with_transport` to get an error instead.
+    pub fn from_data_with_transport<T: AsyncTransport + 'static>(data: Data, transport: T) -> Self {
+        Self::try_from_data_with_transport(data, transport)
+            .expect("access token isn't a valid header value")
+    }
+
+    /// Creates a mastodon instance from the data struct, failing with
+    /// `Error::HeaderValue` if the access token isn't a valid header value.
+    pub fn try_from_data(data: Data) -> Result<Self> {
+        Self::try_from_data_with_transport(data, Client::new())
+    }
+
+    /// Creates a mastodon instance from the data struct, sending all of its
+    /// requests through `transport`. Fails with `Error::HeaderValue` if the
+    /// access token isn't a valid header value.
+    pub fn try_from_data_with_transport<T: AsyncTransport + 'static>(
+        data: Data,
+        transport: T,
+    ) -> Result<Self> {
+        Self::from_data_with_shared_transport(data, Arc::new(transport))
+    }
+
+    /// Creates a client without any credentials, for the public routes of
+    /// the instance at `base`. Every other route fails with
+    /// `Error::AccessTokenRequired`.
+    pub fn unauthenticated<I: Into<Cow<'static, str>>>(base: I) -> Self {
+        Mastodon {
+            transport: Arc::new(Client::new()),
+            session: Session::anonymous(),
+            data: Data {
+                base: base.into(),
+                client_id: "".into(),
+                client_secret: "".into(),
+                redirect: "".into(),
+                token: "".into(),
+                scopes: None,
+                revocation_endpoint: None,
+            },
+        }
+    }
+
+    /// Whether the client has an access token. Routes other than the public
+    /// ones fail with `Error::AccessTokenRequired` without one.
+    pub fn is_authenticated(&self) -> bool {
+        !self.data.token.is_empty()
+    }
+
+    fn from_data_with_shared_transport(
+        data: Data,
+        transport: Arc<dyn AsyncTransport>,
+    ) -> Result<Self> {
+        Ok(Mastodon {
+            transport,
+            session: Session::new(&data.token)?,
+            data,
+        })
+    }
+
+    /// How to retry requests that fail with `429 Too Many Requests` or a
+    /// temporary server error. Defaults to `RetryPolicy::default()`, use
+    /// `RetryPolicy::never()` to disable retries.
+    ///
+    /// Backing off uses `tokio-timer`, so the futures must be run on a tokio
+    /// runtime.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # extern crate reqwest;
+    /// # extern crate tokio;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # use mammut::testing::MockResponse;
+    /// # use reqwest::{Method, StatusCode};
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let data = server.data();
+    /// use std::time::Duration;
+    /// use mammut::r#async::Mastodon;
+    /// use mammut::RetryPolicy;
+    /// use tokio::runtime::Runtime;
+    ///
+    /// let policy = RetryPolicy::new().initial_backoff(Duration::from_millis(10));
+    /// let mastodon = Mastodon::from_data(data).retry_policy(policy);
+    /// # server.mock_once(Method::GET, "/api/v1/instance",
+    /// #     MockResponse::new(StatusCode::SERVICE_UNAVAILABLE));
+    ///
+    /// // The first attempt fails with `503 Service Unavailable`, the second
+    /// // succeeds.
+    /// let instance = Runtime::new()?.block_on(mastodon.instance())?;
+    /// # assert_eq!(server.requests().len(), 2);
+    /// # let _ = instance;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.session.retry = policy;
+        self
+    }
+
+    /// Whether to wait until the rate limit resets when the budget is used
+    /// up, rather than sending requests that will fail with `429 Too Many
+    /// Requests`. A request that is rejected with a 429 anyway is retried once
+    /// the limit resets, counting towards the `max_attempts` of the retry
+    /// policy, after which the 429 is returned. Disabled by default.
+    ///
+    /// Waiting uses `tokio-timer`, so the futures must be run on a tokio
+    /// runtime.
+    pub fn wait_for_rate_limit(mut self, wait: bool) -> Self {
+        self.session.wait_for_rate_limit = wait;
+        self
+    }
+
+    /// The request budget reported with the latest response, if the instance
+    /// sent one. Shared between clones of the client.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.session.rate_limit()
+    }
+
+    routes!(async);
+
+    /// Update the client account's profile.
+    pub fn update_credentials(
+        &self,
+        changes: CredientialsBuilder,
+    ) -> impl Future<Item = Account, Error = Error> {
+        let url = self.route("/api/v1/accounts/update_credentials");
+        let request = self
+            .require_scope("write:accounts")
+            .and_then(|_| changes.into_form())
+            .and_then(|form| Ok(Request::patch(&url)?.multipart(form)));
+
+        self.send(request).and_then(|response| response.json())
+    }
+
+    /// Post a new status to the account.
+    pub fn new_status(&self, status: StatusBuilder) -> impl Future<Item = Status, Error = Error> {
+        let request = self
+            .require_scope("write:statuses")
+            .and_then(|_| Request::post(&self.route("/api/v1/statuses")))
+            .and_then(|request| request.json(&status));
+
+        self.send(request).and_then(|response| response.json())
+    }
+
+    /// Get the federated timeline for the instance.
+    pub fn get_public_timeline(
+        &self,
+        local: bool,
+    ) -> impl Future<Item = Vec<Status>, Error = Error> {
+        let mut url = self.route("/api/v1/timelines/public");
+
+        if local {
+            url += "?local=1";
+        }
+
+        self.get(url)
+    }
+
+    /// Get timeline filtered by a hashtag(eg. `#coffee`) either locally or
+    /// federated.
+    pub fn get_tagged_timeline(
+        &self,
+        hashtag: String,
+        local: bool,
+    ) -> impl Future<Item = Vec<Status>, Error = Error> {
+        let mut url = self.route("/api/v1/timelines/tag/");
+        url += &hashtag;
+
+        if local {
+            url += "?local=1";
+        }
+
+        self.get(url)
+    }
+
+    /// Get statuses of a single account by id. Optionally only with pictures
+    /// and or excluding replies.
+    pub fn statuses<'a, S>(
+        &self,
+        id: &str,
+        request: S,
+    ) -> impl Future<Item = Page<Status>, Error = Error>
+    where
+        S: Into<Option<StatusesRequest<'a>>>,
+    {
+        let mut url = format!("{}/api/v1/accounts/{}/statuses", self.base, id);
+
+        if let Some(request) = request.into() {
+            url = format!("{}{}", url, request.to_querystring());
+        }
+
+        let request = self
+            .require_scope("read:statuses")
+            .and_then(|_| Request::get(&url));
+
+        Page::from_request(self.clone(), request)
+    }
+
+    /// Returns the client account's relationship to a list of other accounts.
+    /// Such as whether they follow them or vice versa.
+    pub fn relationships(
+        &self,
+        ids: &[&str],
+    ) -> impl Future<Item = Page<Relationship>, Error = Error> {
+        let mut url = self.route("/api/v1/accounts/relationships?");
+
+        if ids.len() == 1 {
+            url += "id=";
+            url += ids[0];
+        } else {
+            for id in ids {
+                url += "id[]=";
+                url += id;
+                url += "&";
+            }
+            url.pop();
+        }
+
+        let request = self
+            .require_scope("read:follows")
+            .and_then(|_| Request::get(&url));
+
+        Page::from_request(self.clone(), request)
+    }
+
+    /// Search for accounts by their name.
+    /// Will lookup an account remotely if the search term is in the
+    /// `username@domain` format and not yet in the database.
+    pub fn search_accounts(
+        &self,
+        query: &str,
+        limit: Option<u64>,
+        following: bool,
+    ) -> impl Future<Item = Page<Account>, Error = Error> {
+        let url = format!(
+            "{}/api/v1/accounts/search?q={}&limit={}&following={}",
+            self.base,
+            query,
+            limit.unwrap_or(40),
+            following
+        );
+
+        let request = self
+            .require_scope("read:accounts")
+            .and_then(|_| Request::get(&url));
+
+        Page::from_request(self.clone(), request)
+    }
+
+    /// Build a request to `path`, relative to the instance's base url, for
+    /// routes that don't have a method of their own.
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        RequestBuilder::new(self, method, path)
+    }
+
+    methods![get,];
+
+    // A client for the same app with another access token, keeping the
+    // transport and settings of this one.
+    fn with_token(&self, token: String, scopes: Option<Scopes>) -> Result<Self> {
+        let mut mastodon = self.clone();
+        mastodon.session = self.session.with_token(&token)?;
+        mastodon.data.token = token.into();
+        mastodon.data.scopes = scopes;
+
+        Ok(mastodon)
+    }
+
+    fn route(&self, url: &str) -> String {
+        let mut s = (*self.base).to_owned();
+        s += url;
+        s
+    }
+
+    // Send the request through the transport, with the client's headers.
+    fn send(&self, request: Result<Request>) -> impl Future<Item = Response, Error = Error> {
+        let transport = self.transport.clone();
+        let session = self.session.clone();
+
+        request.into_future().and_then(move |mut request| {
+            session.prepare(&mut request);
+
+            // Each iteration sends the request after waiting for the backoff
+            // of the previous attempt, if it failed, or the rate limit.
+            future::loop_fn(
+                (request, 1, None),
+                move |(request, mut attempt, backoff)| {
+                    let delay = match backoff.or_else(|| session.wait_time()) {
+                        Some(wait_time) => Either::A(
+                            Delay::new(Instant::now() + wait_time)
+                                .map_err(|e| Error::Io(io::Error::new(io::ErrorKind::Other, e))),
+                        ),
+                        None => Either::B(future::ok(())),
+                    };
+
+                    let transport = transport.clone();
+                    let session = session.clone();
+
+                    delay
+                        .and_then(move |_| {
+                            transmit(&transport, request.clone()).map(|r| (request, r))
+                        })
+                        .map(move |(request, response)| {
+                            match session.retry_after(&request, &response, &mut attempt) {
+                                Some(delay) => Loop::Continue((request, attempt, Some(delay))),
+                                None => Loop::Break((request, response)),
+                            }
+                        })
+                },
+            )
+            .and_then(|(request, response)| check_status(&request.method, &request.url, response))
+        })
+    }
+
+    /// Equivalent to /api/v1/media
+    pub fn media(
+        &self,
+        media_builder: MediaBuilder,
+    ) -> impl Future<Item = Attachment, Error = Error> {
+        let url = self.route("/api/v1/media");
+        let request = self
+            .require_scope("write:media")
+            .and_then(|_| Multipart::new().file("file", media_builder.file.as_ref()))
+            .and_then(|mut form_data| {
+                if let Some(description) = media_builder.description {
+                    form_data = form_data.text("description", description);
+                }
+
+                if let Some(focus) = media_builder.focus {
+                    let string = format!("{},{}", focus.0, focus.1);
+                    form_data = form_data.text("focus", string);
+                }
+
+                Ok(Request::post(&url)?.multipart(form_data))
+            });
+
+        self.send(request).and_then(|response| response.json())
+    }
+
+    /// Equivalent to `/api/v1/apps/verify_credentials`, checking the access
+    /// token is still valid and resolving to the app it was given to.
+    pub fn verify_app_credentials(&self) -> impl Future<Item = Application, Error = Error> {
+        let request = self
+            .require_token()
+            .and_then(|_| Request::get(&self.route("/api/v1/apps/verify_credentials")));
+
+        self.send(request).and_then(|response| response.json())
+    }
+
+    /// Revoke the access token, logging the user out of the app, see
+    /// `mammut::Mastodon::revoke`.
+    pub fn revoke(&self) -> impl Future<Item = (), Error = Error> {
+        let request = self
+            .require_token()
+            .and_then(|_| Request::post(&self.revocation_url()))
+            .and_then(|request| {
+                request.form(&[
+                    ("client_id", &*self.client_id),
+                    ("client_secret", &*self.client_secret),
+                    ("token", &*self.token),
+                ])
+            });
+
+        self.send(request).map(|_| ())
+    }
+
+    /// Equivalent to `POST /api/v1/accounts`, signing up a new account and
+    /// resolving to a client with its access token, see
+    /// `mammut::Mastodon::register_account`.
+    pub fn register_account(
+        &self,
+        signup: SignupBuilder,
+    ) -> impl Future<Item = Mastodon, Error = Error> {
+        let request = self
+            .require_scope("write:accounts")
+            .and_then(|_| Request::post(&self.route("/api/v1/accounts")))
+            .and_then(|request| request.json(&signup));
+        let mastodon = self.clone();
+
+        self.send(request)
+            .and_then(|response| response.json::<AccessToken>())
+            .and_then(move |token| mastodon.with_token(token.access_token, token.scope))
+    }
+}
+
+impl fmt::Debug for Mastodon {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Mastodon")
+            .field("transport", &self.transport)
+            .field("headers", &redact::Headers(&self.session.headers))
+            .field("rate_limit", &self.session.rate_limit)
+            .field("wait_for_rate_limit", &self.session.wait_for_rate_limit)
+            .field("retry", &self.session.retry)
+            .field("data", &self.data)
+            .finish()
+    }
+}
+
+impl ops::Deref for Mastodon {
+    type Target = Data;
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+// Send the request, read the full body, and turn error statuses into errors.
+fn fetch(
+    transport: &Arc<dyn AsyncTransport>,
+    request: Request,
+) -> impl Future<Item = Response, Error = Error> {
+    let (method, url) = (request.method.clone(), request.url.clone());
+    transmit(transport, request).and_then(move |response| check_status(&method, &url, response))
+}
+
+// Send the request and read the full body.
+fn transmit(
+    transport: &Arc<dyn AsyncTransport>,
+    request: Request,
+) -> impl Future<Item = Response, Error = Error> {
+    trace!("REQUEST: {:?}", request);
+    let (method, url) = (request.method.clone(), request.url.clone());
+    let start = Instant::now();
+
+    transport.send(request).map(move |response| {
+        log_response(&method, &url, &response, start.elapsed());
+        response
+    })
+}
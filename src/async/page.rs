@@ -0,0 +1,135 @@
+use futures::future::{self, Either};
+use futures::{stream, Future, Stream};
+use serde::Deserialize;
+use url::Url;
+
+use super::Mastodon;
+use crate::page::{get_links, Cursor};
+use crate::transport::Request;
+use crate::{Error, Result};
+
+/// A page of entities returned from a paginated route, along with the links to
+/// the pages before and after it.
+///
+/// Unlike the blocking `Page`, fetching another page doesn't modify this one,
+/// it resolves to a new `Page` instead.
+pub struct Page<T: for<'de> Deserialize<'de>> {
+    mastodon: Mastodon,
+    next: Option<Url>,
+    prev: Option<Url>,
+    /// Initial set of items
+    pub initial_items: Vec<T>,
+}
+
+macro_rules! pages {
+    ($($direction:ident: $fun:ident),*) => {
+
+        $(
+            doc_comment! {
+                concat!("Fetches the ", stringify!($direction), " page of items, resolving to `None` if there is none."),
+            pub fn $fun(&self) -> impl Future<Item = Option<Page<T>>, Error = Error> {
+                match self.$direction.clone() {
+                    Some(url) => {
+                        let request = Request::get(url.as_str());
+
+                        Either::A(Page::from_request(self.mastodon.clone(), request).map(Some))
+                    }
+                    None => Either::B(future::ok(None)),
+                }
+            }
+            }
+         )*
+    }
+}
+
+impl<T: for<'de> Deserialize<'de>> Page<T> {
+    pub(crate) fn from_request(
+        mastodon: Mastodon,
+        request: Result<Request>,
+    ) -> impl Future<Item = Self, Error = Error> {
+        mastodon.send(request).and_then(move |response| {
+            let (prev, next) = get_links(&response.headers)?;
+            Ok(Page {
+                initial_items: response.json()?,
+                next,
+                prev,
+                mastodon,
+            })
+        })
+    }
+
+    pages! {
+        next: next_page,
+        prev: prev_page
+    }
+
+    /// The position of this page, for fetching the pages around it later.
+    /// See the blocking `Cursor` for an example.
+    pub fn cursor(&self) -> Cursor {
+        Cursor::new(self.next.clone(), self.prev.clone())
+    }
+
+    /// Continue from a saved `Cursor`. The page has no items of its own,
+    /// `next_page`, `prev_page` and `items_stream` fetch the pages after and
+    /// before the one the cursor was taken from.
+    pub fn from_cursor(mastodon: Mastodon, cursor: Cursor) -> Self {
+        let (next, prev) = cursor.into_links();
+        Page {
+            mastodon,
+            next,
+            prev,
+            initial_items: Vec::new(),
+        }
+    }
+
+    /// Returns a stream of every `T`, starting with `initial_items` and then
+    /// following the `next` links until there are no more pages.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate mammut;
+    /// # extern crate tokio;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let data = server.data();
+    /// use futures::{Future, Stream};
+    /// use mammut::r#async::Mastodon;
+    /// use tokio::runtime::Runtime;
+    ///
+    /// let mastodon = Mastodon::from_data(data);
+    /// let followers = mastodon
+    ///     .followers("some-id")
+    ///     .map(|page| page.items_stream())
+    ///     .flatten_stream()
+    ///     .for_each(|account| {
+    ///         println!("{}", account.acct);
+    ///         Ok(())
+    ///     });
+    ///
+    /// Runtime::new()?.block_on(followers)?;
+    /// # assert_eq!(server.requests().len(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn items_stream(self) -> impl Stream<Item = T, Error = Error> {
+        let Page {
+            mastodon,
+            next,
+            initial_items,
+            ..
+        } = self;
+
+        let rest = stream::unfold(next, move |next| {
+            let request = Request::get(next?.as_str());
+
+            Some(
+                Page::from_request(mastodon.clone(), request)
+                    .map(|page: Page<T>| (page.initial_items, page.next)),
+            )
+        });
+
+        stream::iter_ok(initial_items).chain(rest.map(stream::iter_ok).flatten())
+    }
+}
@@ -0,0 +1,232 @@
+use std::fmt;
+use std::sync::Arc;
+
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture};
+use reqwest::r#async::Client;
+
+use super::{fetch, Mastodon};
+use crate::apps::{AppBuilder, Scopes};
+use crate::registration::{
+    AccessToken, Authorisation, Grant, OAuth, RegisteredApp, RegistrationState, ServerMetadata,
+};
+use crate::transport::{AsyncTransport, Request};
+use crate::{Error, Result};
+
+/// Handles registering your mastodon app to your instance asynchronously.
+/// Each step consumes the registration and resolves to the next state.
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate mammut;
+/// # extern crate tokio;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let server = mammut::testing::MockServer::start();
+/// use futures::Future;
+/// use mammut::apps::{AppBuilder, Scopes};
+/// use mammut::r#async::Registration;
+/// use tokio::runtime::Runtime;
+///
+/// let app = AppBuilder {
+///     client_name: "mammut_test",
+///     redirect_uris: "urn:ietf:wg:oauth:2.0:oob",
+///     scopes: Scopes::Read,
+///     website: None,
+/// };
+///
+/// # let instance = server.base();
+/// # /*
+/// let instance = "https://mastodon.social";
+/// # */
+/// let mastodon = Registration::new(instance)
+///     .register(app)
+///     .and_then(|mut registration| {
+///         let url = registration.authorise().unwrap();
+///         // Here you now need to open the url in the browser
+///         // And handle a the redirect url coming back with the code.
+///         let code = String::from("RETURNED_FROM_BROWSER");
+///         registration.create_access_token(code)
+///     });
+///
+/// let mastodon = Runtime::new()?.block_on(mastodon)?;
+/// # assert_eq!(mastodon.token, mammut::testing::ACCESS_TOKEN);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Registration {
+    transport: Arc<dyn AsyncTransport>,
+    inner: RegistrationState,
+}
+
+impl fmt::Debug for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt_with_transport(f, &self.transport)
+    }
+}
+
+impl Registration {
+    /// Construct a new registration process to the instance of the `base` url.
+    pub fn new<I: Into<String>>(base: I) -> Self {
+        Self::new_with_transport(base, Client::new())
+    }
+
+    /// Construct a new registration process to the instance of the `base` url,
+    /// sending all of its requests through `transport`. The `Mastodon` client
+    /// created by the registration shares the same transport.
+    pub fn new_with_transport<I, T>(base: I, transport: T) -> Self
+    where
+        I: Into<String>,
+        T: AsyncTransport + 'static,
+    {
+        Registration {
+            transport: Arc::new(transport),
+            inner: RegistrationState::new(base.into()),
+        }
+    }
+
+    /// Continue the registration of an app that is already registered, see
+    /// `mammut::Registration::from_app`.
+    pub fn from_app(app: RegisteredApp) -> Self {
+        Self::from_app_with_transport(app, Client::new())
+    }
+
+    /// Continue the registration of an app that is already registered,
+    /// sending all of its requests through `transport`.
+    pub fn from_app_with_transport<T: AsyncTransport + 'static>(
+        app: RegisteredApp,
+        transport: T,
+    ) -> Self {
+        Registration {
+            transport: Arc::new(transport),
+            inner: RegistrationState::from_app(app),
+        }
+    }
+
+    /// The registered app, to save for `from_app`.
+    ///
+    /// # Errors
+    /// If the app hasn't been registered.
+    pub fn app(&self) -> Result<RegisteredApp> {
+        self.inner.app()
+    }
+
+    /// The authorisation in progress since the latest `authorise`, to save
+    /// until the redirect comes back.
+    pub fn authorisation(&self) -> Option<Authorisation> {
+        self.inner.authorisation()
+    }
+
+    /// Continue an authorisation saved from `authorisation`.
+    pub fn resume(&mut self, authorisation: Authorisation) {
+        self.inner.resume(authorisation)
+    }
+
+    /// Fetch the instance's `ServerMetadata`, resolving to the registration
+    /// with the metadata in use, see `mammut::Registration::discover`.
+    pub fn discover(mut self) -> impl Future<Item = Self, Error = Error> {
+        let transport = self.transport.clone();
+
+        self.inner
+            .discover_request()
+            .into_future()
+            .and_then(move |request| fetch(&transport, request))
+            .and_then(|response| response.json::<ServerMetadata>())
+            .map(move |metadata| {
+                self.inner.discovered(metadata);
+                self
+            })
+    }
+
+    /// The instance's `ServerMetadata`, if it has been discovered.
+    pub fn metadata(&self) -> Option<&ServerMetadata> {
+        self.inner.metadata()
+    }
+
+    /// Register the application with the server from the `base` url.
+    pub fn register(mut self, app_builder: AppBuilder) -> impl Future<Item = Self, Error = Error> {
+        let request = self.inner.register_request(&app_builder);
+        let transport = self.transport.clone();
+
+        request
+            .into_future()
+            .and_then(move |request| fetch(&transport, request))
+            .and_then(|response| response.json::<OAuth>())
+            .map(move |app| {
+                self.inner.registered(app);
+                self
+            })
+    }
+
+    /// Returns the full url needed for authorisation. This needs to be opened
+    /// in a browser. Each call generates a new `state` and PKCE code
+    /// verifier, see `mammut::Registration::authorise`.
+    pub fn authorise(&mut self) -> Result<String> {
+        self.inner.authorise()
+    }
+
+    /// Returns the url needed for authorisation like `authorise`, asking the
+    /// user for only `scopes`, which must be among the scopes the app was
+    /// registered with, see `mammut::Registration::authorise_with_scopes`.
+    pub fn authorise_with_scopes(&mut self, scopes: Scopes) -> Result<String> {
+        self.inner.authorise_for(scopes)
+    }
+
+    /// The authorisation code from the url the browser was redirected to,
+    /// see `mammut::Registration::code_from_redirect`.
+    pub fn code_from_redirect(&self, redirect: &str) -> Result<String> {
+        self.inner.code_from_redirect(redirect)
+    }
+
+    /// Finish authorising from the url the browser was redirected to, and
+    /// create an access token with its code.
+    pub fn complete_authorisation(
+        &self,
+        redirect: &str,
+    ) -> impl Future<Item = Mastodon, Error = Error> {
+        match self.code_from_redirect(redirect) {
+            Ok(code) => Either::A(self.create_access_token(code)),
+            Err(e) => Either::B(future::err(e)),
+        }
+    }
+
+    /// Revoke an access token of the app, see
+    /// `mammut::Registration::revoke`.
+    pub fn revoke(&self, token: &str) -> impl Future<Item = (), Error = Error> {
+        let request = self.inner.revoke_request(token);
+        let transport = self.transport.clone();
+
+        request
+            .into_future()
+            .and_then(move |request| fetch(&transport, request))
+            .map(|_| ())
+    }
+
+    /// Create an access token from the client id, client secret, and code
+    /// provided by the authorisation url. The PKCE code verifier from the
+    /// latest `authorise` is sent along with the code.
+    pub fn create_access_token(&self, code: String) -> impl Future<Item = Mastodon, Error = Error> {
+        self.request_token(self.inner.token_request(&code))
+    }
+
+    /// Create an access token for the app itself rather than a user, with
+    /// the client credentials grant, see
+    /// `mammut::Registration::create_app_token`.
+    pub fn create_app_token(&self) -> impl Future<Item = Mastodon, Error = Error> {
+        self.request_token(self.inner.app_token_request())
+    }
+
+    fn request_token(
+        &self,
+        request: Result<(Request, Grant)>,
+    ) -> impl Future<Item = Mastodon, Error = Error> {
+        let transport = self.transport.clone();
+
+        request.into_future().and_then(move |(request, grant)| {
+            fetch(&transport, request)
+                .and_then(|response| response.json::<AccessToken>())
+                .and_then(move |token| {
+                    Mastodon::from_data_with_shared_transport(grant.complete(token), transport)
+                })
+        })
+    }
+}
@@ -0,0 +1,105 @@
+use futures::Future;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use super::{Mastodon, Page};
+use crate::transport::{Multipart, Request, Response};
+use crate::{Error, Result};
+
+/// A builder pattern struct for sending a request to any route
+/// asynchronously, including ones mammut doesn't have a method for yet.
+/// Created by `Mastodon::request`.
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate mammut;
+/// # extern crate reqwest;
+/// # extern crate tokio;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let data = server.data();
+/// use futures::Future;
+/// use mammut::entities::account::Account;
+/// use mammut::r#async::Mastodon;
+/// use reqwest::Method;
+/// use tokio::runtime::Runtime;
+///
+/// let mastodon = Mastodon::from_data(data);
+/// let account = mastodon
+///     .request(Method::GET, "/api/v1/accounts/1")
+///     .send::<Account>()
+///     .map(|account| println!("{}", account.acct));
+///
+/// Runtime::new()?.block_on(account)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RequestBuilder {
+    mastodon: Mastodon,
+    request: Result<Request>,
+}
+
+impl RequestBuilder {
+    pub(crate) fn new(mastodon: &Mastodon, method: Method, path: &str) -> Self {
+        RequestBuilder {
+            mastodon: mastodon.clone(),
+            request: Request::new(method, &mastodon.route(path)),
+        }
+    }
+
+    /// Append `query` serialised as a url encoded form to the query string.
+    pub fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.request = self.request.and_then(|request| request.query(query));
+        self
+    }
+
+    /// Add a header to the request, replacing the client's header of the
+    /// same name.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        if let Ok(ref mut request) = self.request {
+            request.headers.insert(name, value);
+        }
+        self
+    }
+
+    /// Set the body to `body` serialised as JSON.
+    pub fn json<T: Serialize + ?Sized>(mut self, body: &T) -> Self {
+        self.request = self.request.and_then(|request| request.json(body));
+        self
+    }
+
+    /// Set the body to `body` serialised as a url encoded form.
+    pub fn form<T: Serialize + ?Sized>(mut self, body: &T) -> Self {
+        self.request = self.request.and_then(|request| request.form(body));
+        self
+    }
+
+    /// Set the body to a multipart form, for uploading files.
+    pub fn multipart(mut self, form: Multipart) -> Self {
+        self.request = self.request.map(|request| request.multipart(form));
+        self
+    }
+
+    /// Send the request and deserialise the response body from JSON.
+    pub fn send<T>(self) -> impl Future<Item = T, Error = Error>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.raw().and_then(|response| response.json())
+    }
+
+    /// Send the request to a paginated route, resolving to the first page.
+    pub fn paged<T>(self) -> impl Future<Item = Page<T>, Error = Error>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        Page::from_request(self.mastodon, self.request)
+    }
+
+    /// Send the request, resolving to the response as is.
+    pub fn raw(self) -> impl Future<Item = Response, Error = Error> {
+        self.mastodon.send(self.request)
+    }
+}
@@ -3,7 +3,7 @@
 use std::path::Path;
 
 use chrono::prelude::*;
-use reqwest::multipart::Form;
+use crate::transport::Multipart;
 
 use crate::Result;
 
@@ -52,11 +52,16 @@ pub struct Account {
 /// An extra object given from `verify_credentials` giving defaults about a user
 #[derive(Debug, Clone, Deserialize)]
 pub struct Source {
-    privacy: crate::status_builder::Visibility,
-    sensitive: bool,
-    note: String,
+    /// The default visibility of new statuses.
+    pub privacy: crate::status_builder::Visibility,
+    /// Whether new media is marked sensitive by default.
+    pub sensitive: bool,
+    /// The user's bio, as plain text.
+    pub note: String,
 }
 
+/// Builder for updating the client account's profile, see
+/// `Mastodon::update_credentials`.
 pub struct CredientialsBuilder<'a> {
     display_name: Option<&'a str>,
     note: Option<&'a str>,
@@ -65,8 +70,9 @@ pub struct CredientialsBuilder<'a> {
 }
 
 impl<'a> CredientialsBuilder<'a> {
-    pub fn into_form(self) -> Result<Form> {
-        let mut form = Form::new();
+    /// Convert the changes into a multipart form.
+    pub fn into_form(self) -> Result<Multipart> {
+        let mut form = Multipart::new();
         macro_rules! add_to_form {
             ($key:ident : Text; $($rest:tt)*) => {{
                 if let Some(val) = self.$key {
@@ -44,22 +44,22 @@ fn empty_as_none<'de, D: Deserializer<'de>>(val: D) -> Result<Option<Meta>, D::E
 #[derive(Debug, Deserialize, Clone)]
 pub struct Meta {
     /// Original version.
-    original: ImageDetails,
+    pub original: ImageDetails,
     /// Smaller version.
-    small: ImageDetails,
+    pub small: ImageDetails,
 }
 
 /// Dimensions of an attachement.
 #[derive(Debug, Deserialize, Clone)]
 pub struct ImageDetails {
     /// width of attachment.
-    width: u64,
+    pub width: u64,
     /// height of attachment.
-    height: u64,
+    pub height: u64,
     /// A string of `widthxheight`.
-    size: String,
+    pub size: String,
     /// The aspect ratio of the attachment.
-    aspect: f64,
+    pub aspect: f64,
 }
 
 /// The type of media attachment.
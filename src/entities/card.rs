@@ -12,17 +12,17 @@ pub struct Card {
     /// The image associated with the card, if any.
     pub image: Option<String>,
     /// OEmbed data
-    author_name: Option<String>,
+    pub author_name: Option<String>,
     /// OEmbed data
-    author_url: Option<String>,
+    pub author_url: Option<String>,
     /// OEmbed data
-    provider_name: Option<String>,
+    pub provider_name: Option<String>,
     /// OEmbed data
-    provider_url: Option<String>,
+    pub provider_url: Option<String>,
     /// OEmbed data
-    html: Option<String>,
+    pub html: Option<String>,
     /// OEmbed data
-    width: Option<String>,
+    pub width: Option<String>,
     /// OEmbed data
-    height: Option<String>,
+    pub height: Option<String>,
 }
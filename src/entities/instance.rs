@@ -37,7 +37,10 @@ pub struct StreamingApi {
 /// Statistics about the Mastodon instance.
 #[derive(Debug, Clone, Deserialize)]
 pub struct Stats {
-    user_count: u64,
-    status_count: u64,
-    domain_count: u64,
+    /// The number of users on the instance.
+    pub user_count: u64,
+    /// The number of statuses posted on the instance.
+    pub status_count: u64,
+    /// The number of other instances this one knows about.
+    pub domain_count: u64,
 }
@@ -1,21 +1,17 @@
+use log::debug;
 use serde::Deserialize;
 
-use crate::page::Page;
+use crate::page::TryItemsIter;
 
 /// Abstracts away the `next_page` logic into a single stream of items
 ///
-/// ```no_run
+/// ```
 /// # extern crate mammut;
-/// # use mammut::{Data, Mastodon};
+/// # use mammut::Mastodon;
 /// # use std::error::Error;
-/// # fn main() -> Result<(), Box<Error>> {
-/// # let data = Data {
-/// #   base: "".into(),
-/// #   client_id: "".into(),
-/// #   client_secret: "".into(),
-/// #   redirect: "".into(),
-/// #   token: "".into(),
-/// # };
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let data = server.data();
 /// let client = Mastodon::from_data(data);
 /// let statuses = client.statuses("user-id", None)?;
 /// for status in statuses.items_iter() {
@@ -24,68 +20,35 @@ use crate::page::Page;
 /// # Ok(())
 /// # }
 /// ```
-pub(crate) struct ItemsIter<'a, T: Clone + for<'de> Deserialize<'de>> {
-    page: Page<'a, T>,
-    buffer: Vec<T>,
-    cur_idx: usize,
-    use_initial: bool,
+pub(crate) struct ItemsIter<'a, T: for<'de> Deserialize<'de>> {
+    items: TryItemsIter<'a, T>,
+    failed: bool,
 }
 
-impl<'a, T: Clone + for<'de> Deserialize<'de>> ItemsIter<'a, T> {
-    pub(crate) fn new(page: Page<'a, T>) -> ItemsIter<'a, T> {
+impl<'a, T: for<'de> Deserialize<'de>> ItemsIter<'a, T> {
+    pub(crate) fn new(items: TryItemsIter<'a, T>) -> ItemsIter<'a, T> {
         ItemsIter {
-            page: page,
-            buffer: vec![],
-            cur_idx: 0,
-            use_initial: true,
-        }
-    }
-
-    fn need_next_page(&self) -> bool {
-        self.buffer.is_empty() || self.cur_idx == self.buffer.len()
-    }
-
-    fn fill_next_page(&mut self) -> Option<()> {
-        let items = if let Ok(items) = self.page.next_page() {
-            items
-        } else {
-            return None;
-        };
-        if let Some(items) = items {
-            self.buffer = items;
-            self.cur_idx = 0;
-            Some(())
-        } else {
-            None
+            items,
+            failed: false,
         }
     }
 }
 
-impl<'a, T: Clone + for<'de> Deserialize<'de>> Iterator for ItemsIter<'a, T> {
+impl<'a, T: for<'de> Deserialize<'de>> Iterator for ItemsIter<'a, T> {
     type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.use_initial {
-            if self.page.initial_items.is_empty() {
-                return None;
-            }
-            let idx = self.cur_idx;
-            if self.cur_idx == self.page.initial_items.len() - 1 {
-                self.cur_idx = 0;
-                self.use_initial = false;
-            } else {
-                self.cur_idx += 1;
-            }
-            Some(self.page.initial_items[idx].clone())
-        } else {
-            if self.need_next_page() {
-                if self.fill_next_page().is_none() {
-                    return None;
-                }
+        if self.failed {
+            return None;
+        }
+
+        match self.items.next()? {
+            Ok(item) => Some(item),
+            Err(error) => {
+                debug!("ITEMS ENDED EARLY: {}", error);
+                self.failed = true;
+                None
             }
-            let idx = self.cur_idx;
-            self.cur_idx += 1;
-            Some(self.buffer[idx].clone())
         }
     }
 }
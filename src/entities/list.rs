@@ -1,5 +1,10 @@
+//! Module containing everything related to lists.
+
+/// A user defined list of accounts.
 #[derive(Clone, Debug, Deserialize)]
 pub struct List {
-    id: String,
-    title: String,
+    /// The id of the list.
+    pub id: String,
+    /// The title of the list.
+    pub title: String,
 }
@@ -1,3 +1,6 @@
+//! Module containing everything related to mentions.
+
+/// A mention of another user.
 pub struct Mention {
     /// URL of user's profile (can be remote)
     pub url: String,
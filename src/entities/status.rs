@@ -1,6 +1,7 @@
 //! Module containing all info relating to a status.
 
 use super::prelude::*;
+use crate::apps::Scopes;
 use crate::status_builder::Visibility;
 use chrono::prelude::*;
 
@@ -99,4 +100,15 @@ pub struct Application {
     pub name: String,
     /// Homepage URL of the application.
     pub website: Option<String>,
+    /// The key for Web Push subscriptions, only present when the
+    /// application is verified with `Mastodon::verify_app_credentials`.
+    pub vapid_key: Option<String>,
+    /// The scopes the application was registered with, only present when
+    /// the application is verified with `Mastodon::verify_app_credentials`
+    /// on Mastodon 4.3 and later.
+    #[serde(
+        default,
+        deserialize_with = "crate::apps::scope_list::deserialize_option"
+    )]
+    pub scopes: Option<Scopes>,
 }
@@ -3,9 +3,10 @@
 //! Most of the api is documented on [Mastodon's
 //! github](https://github.com/tootsuite/documentation/blob/master/Using-the-API/API.md)
 //!
-//! ```no_run
+//! ```
 //! # extern crate mammut;
 //! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let server = mammut::testing::MockServer::start();
 //! use mammut::Registration;
 //! use mammut::apps::{AppBuilder, Scopes};
 //!
@@ -16,7 +17,11 @@
 //!     website: None,
 //! };
 //!
-//! let mut registration = Registration::new("https://mastodon.social");
+//! # let instance = server.base();
+//! # /*
+//! let instance = "https://mastodon.social";
+//! # */
+//! let mut registration = Registration::new(instance);
 //! registration.register(app)?;
 //! let url = registration.authorise()?;
 //! // Here you now need to open the url in the browser
@@ -39,38 +44,88 @@ extern crate doc_comment;
 #[macro_use]
 extern crate serde_json as json;
 
+#[macro_use]
+mod macros;
+
 /// Registering your App
 pub mod apps;
+/// Asynchronous client, built on `reqwest`'s async client.
+#[cfg(feature = "async")]
+pub mod r#async;
 /// Entities returned from the API
 pub mod entities;
+/// Capturing the authorisation redirect with a local listener.
+pub mod loopback;
+/// Constructing a client with custom options.
+pub mod mastodon_builder;
 /// Constructing media attachments for a status.
 pub mod media_builder;
 /// Handling multiple pages of entities.
 pub mod page;
+/// Proof Key for Code Exchange, protecting the authorisation code.
+pub mod pkce;
+/// Rate limit tracking.
+pub mod rate_limit;
 /// Registering your app.
 pub mod registration;
+/// Sending requests to any route.
+pub mod request_builder;
+/// Retrying failed requests.
+pub mod retry;
+/// Constructing a sign up for a new account.
+pub mod signup_builder;
 /// Constructing a status
 pub mod status_builder;
+/// Receiving events as they happen from the streaming API.
+pub mod streaming;
+/// A mock Mastodon server for testing.
+#[cfg(feature = "testing")]
+pub mod testing;
+/// Pluggable HTTP transport.
+pub mod transport;
+/// Multiplexing many streams over one WebSocket connection.
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+mod redact;
+mod session;
 
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::error::Error as StdError;
 use std::fmt;
 use std::io::Error as IoError;
 use std::ops;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use hyperx::Error as HyperxError;
 use json::Error as SerdeError;
-use log::debug;
+use log::{debug, trace};
+use reqwest::header::InvalidHeaderValue;
 use reqwest::header::ToStrError as HeaderToStrError;
-use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::header::{self, HeaderMap};
 use reqwest::Error as HttpError;
-use reqwest::{Client, Response, StatusCode};
+use reqwest::{Client, Method, StatusCode};
+use serde_urlencoded::ser::Error as UrlEncodedError;
 use url::ParseError as UrlError;
+use url::Url;
 
+use apps::{Scope, Scopes};
 use entities::prelude::*;
+pub use mastodon_builder::MastodonBuilder;
 pub use media_builder::MediaBuilder;
-use page::Page;
+use page::{Page, Poll};
+pub use rate_limit::RateLimit;
+use registration::AccessToken;
+pub use request_builder::RequestBuilder;
+pub use retry::RetryPolicy;
+use session::Session;
+pub use signup_builder::SignupBuilder;
 pub use status_builder::StatusBuilder;
+use streaming::{EventStream, Stream};
+use transport::{Multipart, Request, Response, Transport};
 
 pub use registration::Registration;
 /// Convience type over `std::result::Result` with `Error` as the error type.
@@ -82,151 +137,66 @@ macro_rules! methods {
             fn $method<T: for<'de> serde::Deserialize<'de>>(&self, url: String)
             -> Result<T>
             {
-                let request = self.client.$method(&url)
-                    .headers(self.headers.clone());
-                debug!("REQUEST: {:?}", request);
-
-                let response = request.send()?;
-                debug!("RESPONSE: {:?}", response);
-
-                deserialise(response)
+                self.send(Request::$method(&url)?)?.json()
             }
          )+
     };
 }
 
-macro_rules! paged_routes {
-
-    (($method:ident) $name:ident: $url:expr => $ret:ty, $($rest:tt)*) => {
-        doc_comment! {
-            concat!(
-                "Equivalent to `/api/v1/",
-                $url,
-                "`\n# Errors\nIf `access_token` is not set."),
-            pub fn $name(&self) -> Result<Page<$ret>> {
-                let url = self.route(concat!("/api/v1/", $url));
-                let response = self.client.$method(&url)
-                    .headers(self.headers.clone())
-                    .send()?;
-
-                Page::new(self, response)
-            }
-
-        }
-
-        paged_routes!{$($rest)*}
-    };
-
-    () => {}
-}
-
-macro_rules! route {
-
-    (($method:ident ($($param:ident: $typ:ty,)*)) $name:ident: $url:expr => $ret:ty, $($rest:tt)*) => {
-        doc_comment! {
-            concat!(
-                "Equivalent to `/api/v1/",
-                $url,
-                "`\n# Errors\nIf `access_token` is not set."),
-
-            pub fn $name(&self, $($param: $typ,)*) -> Result<$ret> {
-
-                let form_data = json!({
-                    $(
-                        stringify!($param): $param,
-                    )*
-                });
-
-                let response = self.client.$method(&self.route(concat!("/api/v1/", $url)))
-                    .headers(self.headers.clone())
-                    .json(&form_data)
-                    .send()?;
-
-                let status = response.status().clone();
-
-                if status.is_client_error() {
-                    return Err(Error::Client(status));
-                } else if status.is_server_error() {
-                    return Err(Error::Server(status));
-                }
-
-                deserialise(response)
-            }
-        }
-
-        route!{$($rest)*}
-    };
-
-    (($method:ident) $name:ident: $url:expr => $ret:ty, $($rest:tt)*) => {
-        doc_comment! {
-            concat!(
-                "Equivalent to `/api/v1/",
-                $url,
-                "`\n# Errors\nIf `access_token` is not set."),
-            pub fn $name(&self) -> Result<$ret> {
-                self.$method(self.route(concat!("/api/v1/", $url)))
-            }
-        }
-
-        route!{$($rest)*}
-    };
-
-    () => {}
-}
-
-macro_rules! route_id {
-
-    ($(($method:ident) $name:ident: $url:expr => $ret:ty,)*) => {
-        $(
-            doc_comment! {
-                concat!(
-                    "Equivalent to `/api/v1/",
-                    $url,
-                    "`\n# Errors\nIf `access_token` is not set."),
-                pub fn $name(&self, id: &str) -> Result<$ret> {
-                    self.$method(self.route(&format!(concat!("/api/v1/", $url), id)))
-                }
-            }
-         )*
-    }
-
-}
-macro_rules! paged_routes_with_id {
-
-    (($method:ident) $name:ident: $url:expr => $ret:ty, $($rest:tt)*) => {
-        doc_comment! {
-            concat!(
-                "Equivalent to `/api/v1/",
-                $url,
-                "`\n# Errors\nIf `access_token` is not set."),
-            pub fn $name(&self, id: &str) -> Result<Page<$ret>> {
-                let url = self.route(&format!(concat!("/api/v1/", $url), id));
-                let response = self.client.$method(&url)
-                    .headers(self.headers.clone())
-                    .send()?;
-
-                Page::new(self, response)
-            }
-        }
-
-        paged_routes_with_id!{$($rest)*}
-    };
-
-    () => {}
-}
-
 /// Your mastodon application client, handles all requests to and from Mastodon.
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Mastodon {
-    client: Client,
-    headers: HeaderMap,
+    transport: Arc<dyn Transport>,
+    session: Session,
+    #[cfg(feature = "websocket")]
+    connection: websocket::Connection,
     /// Raw data about your mastodon instance.
     pub data: Data,
 }
 
 /// Raw data about mastodon app. Save `Data` using `serde` to prevent needing
 /// to authenticate on every run.
-#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+///
+/// The `Debug` output of `Data` has the client secret and access token
+/// redacted, as does the output of `Mastodon`, `Registration` and `Error`.
+///
+/// ```
+/// # extern crate mammut;
+/// # let server = mammut::testing::MockServer::start();
+/// # let data = server.data();
+/// let debug = format!("{:?}", data);
+/// assert!(!debug.contains(&*data.token));
+/// assert!(!debug.contains(&*data.client_secret));
+///
+/// # let debug = format!("{:?}", mammut::Mastodon::from_data(data.clone()));
+/// # assert!(!debug.contains(&*data.token));
+/// # assert!(!debug.contains(&*data.client_secret));
+/// ```
+///
+/// `Registration` records the scopes the access token was granted in
+/// `scopes`, so a route needing another scope fails straight away.
+///
+/// ```
+/// # extern crate mammut;
+/// # fn main() -> mammut::Result<()> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let mut data = server.data();
+/// use mammut::apps::{Scope, Scopes};
+/// use mammut::{Error, Mastodon, StatusBuilder};
+///
+/// data.scopes = Some(Scopes::Read);
+/// let mastodon = Mastodon::from_data(data);
+/// mastodon.get_home_timeline()?;
+///
+/// match mastodon.new_status(StatusBuilder::new("Hello".into())) {
+///     Err(Error::InsufficientScope(scope)) => assert_eq!(scope, Scope::write("statuses")),
+///     other => panic!("{:?}", other),
+/// }
+/// # assert_eq!(server.requests().len(), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
 pub struct Data {
     /// Base url of instance eg. `https://mastodon.social`.
     pub base: Cow<'static, str>,
@@ -236,80 +206,207 @@ pub struct Data {
     pub client_secret: Cow<'static, str>,
     /// Url to redirect back to your application from the instance signup.
     pub redirect: Cow<'static, str>,
-    /// The client's access token.
+    /// The client's access token, empty for an unauthenticated client.
     pub token: Cow<'static, str>,
+    /// The scopes the access token was granted, if known. Routes needing a
+    /// scope that isn't among them fail with `Error::InsufficientScope`
+    /// without sending a request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Scopes>,
+    /// The url for revoking the access token, if the instance advertised one
+    /// in its `ServerMetadata`. `Mastodon::revoke` uses `/oauth/revoke`
+    /// without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation_endpoint: Option<Cow<'static, str>>,
+}
+
+impl fmt::Debug for Data {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Data")
+            .field("base", &self.base)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &redact::Secret(&self.client_secret))
+            .field("redirect", &self.redirect)
+            .field("token", &redact::Secret(&self.token))
+            .field("scopes", &self.scopes)
+            .field("revocation_endpoint", &self.revocation_endpoint)
+            .finish()
+    }
 }
 
 /// enum of possible errors encountered using the mastodon API.
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
 pub enum Error {
-    /// Error from the Mastodon API. This typically means something went
-    /// wrong with your authentication or data.
-    Api(ApiError),
+    /// The instance responded with a status other than `2xx`. This typically
+    /// means something went wrong with your authentication or data.
+    Response(Box<ResponseError>),
     /// Error deserialising to json. Typically represents a breaking change in
     /// the Mastodon API
-    #[serde(skip_deserializing)]
     Serde(SerdeError),
     /// Error encountered in the HTTP backend while requesting a route.
-    #[serde(skip_deserializing)]
     Http(HttpError),
     /// Wrapper around the `std::io::Error` struct.
-    #[serde(skip_deserializing)]
     Io(IoError),
     /// Wrapper around the `url::ParseError` struct.
-    #[serde(skip_deserializing)]
     Url(UrlError),
     /// Missing Client Id.
-    #[serde(skip_deserializing)]
     ClientIdRequired,
     /// Missing Client Secret.
-    #[serde(skip_deserializing)]
     ClientSecretRequired,
     /// Missing Access Token.
-    #[serde(skip_deserializing)]
     AccessTokenRequired,
-    /// Generic client error.
-    #[serde(skip_deserializing)]
-    Client(StatusCode),
-    /// Generic server error.
-    #[serde(skip_deserializing)]
-    Server(StatusCode),
+    /// The access token wasn't granted the scope a route needs, according to
+    /// `Data::scopes`.
+    InsufficientScope(Scope),
+    /// The user didn't authorise the app, or the instance couldn't. Holds the
+    /// instance's description of the error.
+    AuthorisationDenied(String),
+    /// The redirect from the authorisation url has no authorisation code.
+    CodeRequired,
+    /// A scope isn't among those the instance supports, according to its
+    /// `registration::ServerMetadata`.
+    UnsupportedScope(Scope),
+    /// A scope asked for when authorising isn't among those the app was
+    /// registered with.
+    UnregisteredScope(Scope),
+    /// The `state` in the redirect from the authorisation url isn't the one
+    /// sent with it, so the redirect may be forged.
+    StateMismatch,
     /// A possible error when converting a HeaderValue to a string representation.
-    #[serde(skip_deserializing)]
     Header(HeaderToStrError),
+    /// A string that isn't valid as the value of a header.
+    HeaderValue(InvalidHeaderValue),
+    /// Timeouts or proxies were set on a `MastodonBuilder` that was also given
+    /// a shared client, which keeps its own.
+    SharedClientConflict,
     /// Errors while parsing headers and associated types.
-    #[serde(skip_deserializing)]
     Hyperx(HyperxError),
+    /// Error serialising a url encoded form.
+    UrlEncoded(UrlEncodedError),
+    /// The streaming API refused a subscription. Holds the refused stream, if
+    /// the instance named it, and the instance's description of the error.
+    #[cfg(feature = "websocket")]
+    SubscriptionRefused(Option<Stream>, String),
+    /// Error from the WebSocket connection to the streaming API.
+    #[cfg(feature = "websocket")]
+    WebSocket(tungstenite::Error),
+    /// Proxies were set on the `MastodonBuilder` of a client opening a
+    /// WebSocket connection, which can't be sent through them.
+    #[cfg(feature = "websocket")]
+    WebSocketProxy,
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match *self {
+            Error::Response(ref e) => e.fmt(f),
+            Error::Serde(ref e) => write!(f, "couldn't deserialise the response: {}", e),
+            Error::Http(ref e) => redact::HttpError(e).fmt(f),
+            Error::Io(ref e) => e.fmt(f),
+            Error::Url(ref e) => write!(f, "invalid url: {}", e),
+            Error::ClientIdRequired => {
+                f.write_str("the app hasn't been registered, missing client id")
+            }
+            Error::ClientSecretRequired => {
+                f.write_str("the app hasn't been registered, missing client secret")
+            }
+            Error::AccessTokenRequired => f.write_str("this route requires an access token"),
+            Error::InsufficientScope(ref scope) => {
+                write!(f, "this route requires the `{}` scope", scope)
+            }
+            Error::AuthorisationDenied(ref e) => write!(f, "authorisation failed: {}", e),
+            Error::CodeRequired => f.write_str("the redirect has no authorisation code"),
+            Error::UnsupportedScope(ref scope) => {
+                write!(f, "the instance doesn't support the `{}` scope", scope)
+            }
+            Error::UnregisteredScope(ref scope) => {
+                write!(f, "the app wasn't registered with the `{}` scope", scope)
+            }
+            Error::StateMismatch => {
+                f.write_str("the redirect's state doesn't match the one sent with it")
+            }
+            Error::Header(ref e) => write!(f, "couldn't read a header: {}", e),
+            Error::HeaderValue(ref e) => write!(f, "invalid header value: {}", e),
+            Error::SharedClientConflict => {
+                f.write_str("timeouts and proxies can't be set along with a shared client")
+            }
+            Error::Hyperx(ref e) => write!(f, "couldn't parse a header: {}", e),
+            Error::UrlEncoded(ref e) => write!(f, "couldn't encode the form: {}", e),
+            #[cfg(feature = "websocket")]
+            Error::SubscriptionRefused(Some(ref stream), ref e) => {
+                write!(f, "couldn't subscribe to the `{}` stream: {}", stream, e)
+            }
+            #[cfg(feature = "websocket")]
+            Error::SubscriptionRefused(None, ref e) => {
+                write!(f, "couldn't subscribe to a stream: {}", e)
+            }
+            #[cfg(feature = "websocket")]
+            Error::WebSocket(ref e) => write!(f, "streaming connection failed: {}", e),
+            #[cfg(feature = "websocket")]
+            Error::WebSocketProxy => {
+                f.write_str("WebSocket connections can't be sent through a proxy")
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Response(ref e) => f.debug_tuple("Response").field(e).finish(),
+            Error::Serde(ref e) => f.debug_tuple("Serde").field(e).finish(),
+            Error::Http(ref e) => f.debug_tuple("Http").field(&redact::HttpError(e)).finish(),
+            Error::Io(ref e) => f.debug_tuple("Io").field(e).finish(),
+            Error::Url(ref e) => f.debug_tuple("Url").field(e).finish(),
+            Error::ClientIdRequired => f.write_str("ClientIdRequired"),
+            Error::ClientSecretRequired => f.write_str("ClientSecretRequired"),
+            Error::AccessTokenRequired => f.write_str("AccessTokenRequired"),
+            Error::InsufficientScope(ref scope) => {
+                f.debug_tuple("InsufficientScope").field(scope).finish()
+            }
+            Error::AuthorisationDenied(ref e) => {
+                f.debug_tuple("AuthorisationDenied").field(e).finish()
+            }
+            Error::CodeRequired => f.write_str("CodeRequired"),
+            Error::UnsupportedScope(ref scope) => {
+                f.debug_tuple("UnsupportedScope").field(scope).finish()
+            }
+            Error::UnregisteredScope(ref scope) => {
+                f.debug_tuple("UnregisteredScope").field(scope).finish()
+            }
+            Error::StateMismatch => f.write_str("StateMismatch"),
+            Error::Header(ref e) => f.debug_tuple("Header").field(e).finish(),
+            Error::HeaderValue(ref e) => f.debug_tuple("HeaderValue").field(e).finish(),
+            Error::SharedClientConflict => f.write_str("SharedClientConflict"),
+            Error::Hyperx(ref e) => f.debug_tuple("Hyperx").field(e).finish(),
+            Error::UrlEncoded(ref e) => f.debug_tuple("UrlEncoded").field(e).finish(),
+            #[cfg(feature = "websocket")]
+            Error::SubscriptionRefused(ref stream, ref e) => f
+                .debug_tuple("SubscriptionRefused")
+                .field(stream)
+                .field(e)
+                .finish(),
+            #[cfg(feature = "websocket")]
+            Error::WebSocket(ref e) => f.debug_tuple("WebSocket").field(e).finish(),
+            #[cfg(feature = "websocket")]
+            Error::WebSocketProxy => f.write_str("WebSocketProxy"),
+        }
     }
 }
 
 impl StdError for Error {
-    fn description(&self) -> &str {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match *self {
-            Error::Api(ref e) => e
-                .error_description
-                .as_ref()
-                .map(|i| &**i)
-                .or(e.error.as_ref().map(|i| &**i))
-                .unwrap_or("Unknown API Error"),
-            Error::Serde(ref e) => e.description(),
-            Error::Http(ref e) => e.description(),
-            Error::Io(ref e) => e.description(),
-            Error::Url(ref e) => e.description(),
-            Error::Client(ref status) | Error::Server(ref status) => {
-                status.canonical_reason().unwrap_or("Unknown Status code")
-            }
-            Error::Hyperx(ref e) => e.description(),
-            Error::Header(ref e) => e.description(),
-            Error::ClientIdRequired => "ClientIdRequired",
-            Error::ClientSecretRequired => "ClientSecretRequired",
-            Error::AccessTokenRequired => "AccessTokenRequired",
+            Error::Serde(ref e) => Some(e),
+            Error::Http(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+            Error::Url(ref e) => Some(e),
+            Error::Hyperx(ref e) => Some(e),
+            Error::Header(ref e) => Some(e),
+            Error::HeaderValue(ref e) => Some(e),
+            Error::UrlEncoded(ref e) => Some(e),
+            #[cfg(feature = "websocket")]
+            Error::WebSocket(ref e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -320,12 +417,136 @@ impl From<HyperxError> for Error {
     }
 }
 
+#[cfg(feature = "websocket")]
+impl From<tungstenite::Error> for Error {
+    fn from(error: tungstenite::Error) -> Self {
+        Error::WebSocket(error)
+    }
+}
+
 impl From<HeaderToStrError> for Error {
     fn from(error: HeaderToStrError) -> Self {
         Error::Header(error)
     }
 }
 
+/// A response from the instance with a status other than `2xx`.
+///
+/// ```
+/// # extern crate mammut;
+/// # #[macro_use] extern crate serde_json;
+/// # extern crate reqwest;
+/// # fn main() -> mammut::Result<()> {
+/// # use mammut::testing::MockResponse;
+/// # use reqwest::{Method, StatusCode};
+/// # let server = mammut::testing::MockServer::start();
+/// # server.mock(Method::POST, "/api/v1/statuses", MockResponse::json(
+/// #     StatusCode::UNPROCESSABLE_ENTITY,
+/// #     &json!({
+/// #         "error": "Validation failed: Text character limit of 500 exceeded",
+/// #         "details": {
+/// #             "text": [{"error": "ERR_TOO_LONG", "description": "Text is too long"}]
+/// #         }
+/// #     })));
+/// # let mastodon = server.mastodon();
+/// use mammut::{Error, StatusBuilder};
+///
+/// let status = StatusBuilder::new("a".repeat(501));
+///
+/// match mastodon.new_status(status) {
+///     Err(Error::Response(error)) => {
+///         // POST http://127.0.0.1:4000/api/v1/statuses failed with 422
+///         // Unprocessable Entity: Validation failed: Text character limit of
+///         // 500 exceeded
+///         println!("{}", error);
+///
+///         if let Some(details) = error.api_error.and_then(|error| error.details) {
+///             for (attribute, errors) in details {
+///                 # assert_eq!(attribute, "text");
+///                 # assert_eq!(errors[0].error, "ERR_TOO_LONG");
+///                 println!("{}: {}", attribute, errors[0].description);
+///             }
+///         }
+///     }
+///     # Ok(_) => unreachable!(),
+///     other => { other?; }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ResponseError {
+    /// The status of the response.
+    pub status: StatusCode,
+    /// The method of the request.
+    pub method: Method,
+    /// The url of the request.
+    pub url: Url,
+    /// The error from the response body, if it was one Mastodon sends.
+    pub api_error: Option<ApiError>,
+    /// The headers of the response relevant to the error: `Retry-After`,
+    /// `WWW-Authenticate`, `X-Request-Id` and the `X-RateLimit-*` headers.
+    pub headers: HeaderMap,
+    /// The full response body.
+    pub body: Vec<u8>,
+}
+
+impl ResponseError {
+    fn new(method: Method, url: Url, response: Response) -> Self {
+        let mut headers = HeaderMap::new();
+
+        for (name, value) in &response.headers {
+            let relevant = name == header::RETRY_AFTER
+                || name == header::WWW_AUTHENTICATE
+                || name == "x-request-id"
+                || name.as_str().starts_with("x-ratelimit-");
+
+            if relevant {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+
+        ResponseError {
+            status: response.status,
+            method,
+            url,
+            api_error: json::from_slice(&response.body).ok(),
+            headers,
+            body: response.body,
+        }
+    }
+
+    /// The request budget reported with the response, if any.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        RateLimit::from_headers(&self.headers)
+    }
+}
+
+impl fmt::Debug for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResponseError")
+            .field("status", &self.status)
+            .field("method", &self.method)
+            .field("url", &redact::Link(&self.url))
+            .field("api_error", &self.api_error)
+            .field("headers", &redact::Headers(&self.headers))
+            .field("body", &format_args!("{} bytes", self.body.len()))
+            .finish()
+    }
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let url = redact::Link(&self.url);
+        write!(f, "{} {} failed with {}", self.method, url, self.status)?;
+
+        match self.api_error {
+            Some(ref error) => write!(f, ": {}", error),
+            None => Ok(()),
+        }
+    }
+}
+
 /// Error returned from the Mastodon API.
 #[derive(Clone, Debug, Deserialize)]
 pub struct ApiError {
@@ -333,6 +554,38 @@ pub struct ApiError {
     pub error: Option<String>,
     /// The description of the error.
     pub error_description: Option<String>,
+    /// Why each invalid attribute was rejected, sent along with
+    /// `422 Unprocessable Entity`.
+    pub details: Option<HashMap<String, Vec<ValidationError>>>,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut messages = self
+            .error
+            .iter()
+            .chain(self.error_description.iter())
+            .map(|message| &message[..]);
+
+        if let Some(message) = messages.next() {
+            f.write_str(message)?;
+        }
+
+        for message in messages {
+            write!(f, ", {}", message)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Why an attribute of a request was rejected.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ValidationError {
+    /// The kind of error, such as `ERR_TOO_LONG` or `ERR_BLANK`.
+    pub error: String,
+    /// A description of the error.
+    pub description: String,
 }
 
 /// # Example
@@ -359,50 +612,61 @@ pub struct StatusesRequest<'a> {
 }
 
 impl<'a> StatusesRequest<'a> {
+    /// Construct an empty request, with all filters disabled.
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Only return statuses that have media attachments.
     pub fn only_media(mut self) -> Self {
         self.only_media = true;
         self
     }
 
+    /// Skip statuses that reply to other statuses.
     pub fn exclude_replies(mut self) -> Self {
         self.exclude_replies = true;
         self
     }
 
+    /// Only return statuses that have been pinned.
     pub fn pinned(mut self) -> Self {
         self.pinned = true;
         self
     }
 
+    /// Return results older than this ID.
     pub fn max_id<S: Into<Cow<'a, str>>>(mut self, max_id: S) -> Self {
         self.max_id = Some(max_id.into());
         self
     }
 
+    /// Return results newer than this ID.
     pub fn since_id<S: Into<Cow<'a, str>>>(mut self, since_id: S) -> Self {
         self.since_id = Some(since_id.into());
         self
     }
 
+    /// Return results immediately newer than this ID.
     pub fn min_id<S: Into<Cow<'a, str>>>(mut self, min_id: S) -> Self {
         self.min_id = Some(min_id.into());
         self
     }
 
+    /// Maximum number of results to return.
     pub fn limit(mut self, limit: usize) -> Self {
         self.limit = Some(limit);
         self
     }
 
+    /// Skip statuses that reblog other statuses.
     pub fn exclude_reblogs(mut self) -> Self {
         self.exclude_reblogs = true;
         self
     }
 
+    /// Serialise the request into a query string, including the leading
+    /// `?` when any options are set.
     pub fn to_querystring(&self) -> String {
         let mut opts = vec![];
 
@@ -447,130 +711,193 @@ impl<'a> StatusesRequest<'a> {
 }
 
 impl Mastodon {
-    fn from_registration<I>(
-        base: I,
-        client_id: I,
-        client_secret: I,
-        redirect: I,
-        token: I,
-        client: Client,
-    ) -> Self
-    where
-        I: Into<Cow<'static, str>>,
-    {
-        let data = Data {
-            base: base.into(),
-            client_id: client_id.into(),
-            client_secret: client_secret.into(),
-            redirect: redirect.into(),
-            token: token.into(),
-        };
+    /// Creates a mastodon instance from the data struct.
+    ///
+    /// # Panics
+    /// If the access token isn't a valid header value. Use `try_from_data`
+    /// or `MastodonBuilder` to get an error instead.
+    pub fn from_data(data: Data) -> Self {
+        Self::try_from_data(data).expect("access token isn't a valid header value")
+    }
 
-        let mut headers = HeaderMap::new();
-        let auth = HeaderValue::from_str(&format!("Bearer {}", data.token));
-        headers.insert(header::AUTHORIZATION, auth.unwrap());
+    /// Creates a mastodon instance from the data struct, sending all of its
+    /// requests through `transport`.
+    ///
+    /// # Panics
+    /// If the access token isn't a valid header value. Use
+    /// `try_from_data_with_transport` to get an error instead.
+    pub fn from_data_with_transport<T: Transport + 'static>(data: Data, transport: T) -> Self {
+        Self::try_from_data_with_transport(data, transport)
+            .expect("access token isn't a valid header value")
+    }
 
-        Mastodon {
-            client: client,
-            headers: headers,
-            data: data,
-        }
+    /// Creates a mastodon instance from the data struct, failing with
+    /// `Error::HeaderValue` if the access token isn't a valid header value.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// use mammut::{Data, Error, Mastodon};
+    ///
+    /// let data = Data {
+    ///     base: "https://mastodon.social".into(),
+    ///     client_id: "".into(),
+    ///     client_secret: "".into(),
+    ///     redirect: "".into(),
+    ///     token: "not\na token".into(),
+    ///     scopes: None,
+    ///     revocation_endpoint: None,
+    /// };
+    ///
+    /// match Mastodon::try_from_data(data) {
+    ///     Err(Error::HeaderValue(_)) => {}
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn try_from_data(data: Data) -> Result<Self> {
+        Self::try_from_data_with_transport(data, Client::new())
     }
 
-    /// Creates a mastodon instance from the data struct.
-    pub fn from_data(data: Data) -> Self {
-        let mut headers = HeaderMap::new();
-        let auth = HeaderValue::from_str(&format!("Bearer {}", data.token));
-        headers.insert(header::AUTHORIZATION, auth.unwrap());
+    /// Creates a mastodon instance from the data struct, sending all of its
+    /// requests through `transport`. Fails with `Error::HeaderValue` if the
+    /// access token isn't a valid header value.
+    pub fn try_from_data_with_transport<T: Transport + 'static>(
+        data: Data,
+        transport: T,
+    ) -> Result<Self> {
+        Self::from_data_with_shared_transport(data, Arc::new(transport))
+    }
+
+    /// Creates a client without any credentials, for the public routes of
+    /// the instance at `base`, eg. `https://mastodon.social`. Every other
+    /// route fails with `Error::AccessTokenRequired`.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # fn main() -> mammut::Result<()> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let instance = server.base().to_owned();
+    /// # /*
+    /// let instance = "https://mastodon.social";
+    /// # */
+    /// use mammut::{Error, Mastodon};
+    ///
+    /// let mastodon = Mastodon::unauthenticated(instance);
+    /// let timeline = mastodon.get_public_timeline(false)?;
+    ///
+    /// match mastodon.verify_credentials() {
+    ///     Err(Error::AccessTokenRequired) => {}
+    ///     _ => unreachable!(),
+    /// }
+    /// # assert!(server.requests().iter().all(|r| !r.headers.contains_key("authorization")));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn unauthenticated<I: Into<Cow<'static, str>>>(base: I) -> Self {
+        Self::with_session(
+            Data {
+                base: base.into(),
+                client_id: "".into(),
+                client_secret: "".into(),
+                redirect: "".into(),
+                token: "".into(),
+                scopes: None,
+                revocation_endpoint: None,
+            },
+            Arc::new(Client::new()),
+            Session::anonymous(),
+        )
+    }
+
+    /// Whether the client has an access token. Routes other than the public
+    /// ones fail with `Error::AccessTokenRequired` without one.
+    pub fn is_authenticated(&self) -> bool {
+        !self.data.token.is_empty()
+    }
 
+    fn from_data_with_shared_transport(
+        data: Data,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Self> {
+        let session = Session::new(&data.token)?;
+        Ok(Self::with_session(data, transport, session))
+    }
+
+    fn with_session(data: Data, transport: Arc<dyn Transport>, session: Session) -> Self {
         Mastodon {
-            client: Client::new(),
-            headers: headers,
-            data: data,
+            transport,
+            session,
+            #[cfg(feature = "websocket")]
+            connection: websocket::Connection::default(),
+            data,
         }
     }
 
-    paged_routes! {
-        (get) favourites: "favourites" => Status,
-        (get) blocks: "blocks" => Account,
-        (get) domain_blocks: "domain_blocks" => String,
-        (get) follow_requests: "follow_requests" => Account,
-        (get) get_home_timeline: "timelines/home" => Status,
-        (get) get_emojis: "custom_emojis" => Emoji,
-        (get) mutes: "mutes" => Account,
-        (get) notifications: "notifications" => Notification,
-        (get) reports: "reports" => Report,
-    }
-
-    paged_routes_with_id! {
-        (get) followers: "accounts/{}/followers" => Account,
-        (get) following: "accounts/{}/following" => Account,
-        (get) reblogged_by: "statuses/{}/reblogged_by" => Account,
-        (get) favourited_by: "statuses/{}/favourited_by" => Account,
-    }
-
-    route! {
-        (delete (domain: String,)) unblock_domain: "domain_blocks" => Empty,
-        (get) instance: "instance" => Instance,
-        (get) verify_credentials: "accounts/verify_credentials" => Account,
-        (post (account_id: &str, status_ids: Vec<&str>, comment: String,)) report: "reports" => Report,
-        (post (domain: String,)) block_domain: "domain_blocks" => Empty,
-        (post (id: &str,)) authorize_follow_request: "accounts/follow_requests/authorize" => Empty,
-        (post (id: &str,)) reject_follow_request: "accounts/follow_requests/reject" => Empty,
-        (post (q: String, resolve: bool,)) search: "search" => SearchResult,
-        (post (uri: Cow<'static, str>,)) follows: "follows" => Account,
-        (post) clear_notifications: "notifications/clear" => Empty,
-    }
-
-    route_id! {
-        (get) get_account: "accounts/{}" => Account,
-        (post) follow: "accounts/{}/follow" => Account,
-        (post) unfollow: "accounts/{}/unfollow" => Account,
-        (get) block: "accounts/{}/block" => Account,
-        (get) unblock: "accounts/{}/unblock" => Account,
-        (get) mute: "accounts/{}/mute" => Account,
-        (get) unmute: "accounts/{}/unmute" => Account,
-        (get) get_notification: "notifications/{}" => Notification,
-        (get) get_status: "statuses/{}" => Status,
-        (get) get_context: "statuses/{}/context" => Context,
-        (get) get_card: "statuses/{}/card" => Card,
-        (post) reblog: "statuses/{}/reblog" => Status,
-        (post) unreblog: "statuses/{}/unreblog" => Status,
-        (post) favourite: "statuses/{}/favourite" => Status,
-        (post) unfavourite: "statuses/{}/unfavourite" => Status,
-        (delete) delete_status: "statuses/{}" => Empty,
+    /// How to retry requests that fail with `429 Too Many Requests` or a
+    /// temporary server error. Defaults to `RetryPolicy::default()`, use
+    /// `RetryPolicy::never()` to disable retries.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.session.retry = policy;
+        self
+    }
+
+    /// Whether to sleep until the rate limit resets when the budget is used up,
+    /// rather than sending requests that will fail with `429 Too Many
+    /// Requests`. A request that is rejected with a 429 anyway is retried once
+    /// the limit resets, counting towards the `max_attempts` of the retry
+    /// policy, after which the 429 is returned. Disabled by default.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # extern crate reqwest;
+    /// # fn main() -> mammut::Result<()> {
+    /// # use mammut::testing::MockResponse;
+    /// # use reqwest::{Method, StatusCode};
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let reset = std::time::SystemTime::now()
+    /// #     .duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() + 1;
+    /// # server.mock_once(Method::GET, "/api/v1/timelines/home",
+    /// #     MockResponse::new(StatusCode::TOO_MANY_REQUESTS)
+    /// #         .header("X-RateLimit-Limit", "300")
+    /// #         .header("X-RateLimit-Remaining", "0")
+    /// #         .header("X-RateLimit-Reset", reset.to_string()));
+    /// # let data = server.data();
+    /// use mammut::Mastodon;
+    ///
+    /// let mastodon = Mastodon::from_data(data).wait_for_rate_limit(true);
+    /// // Blocks until the limit resets if the budget has been used up.
+    /// mastodon.get_home_timeline()?;
+    /// # assert_eq!(server.requests().len(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn wait_for_rate_limit(mut self, wait: bool) -> Self {
+        self.session.wait_for_rate_limit = wait;
+        self
+    }
+
+    /// The request budget reported with the latest response, if the instance
+    /// sent one. Shared between clones of the client.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.session.rate_limit()
     }
 
+    routes!(blocking);
+
+    /// Update the client account's profile.
     pub fn update_credentials(&self, changes: CredientialsBuilder) -> Result<Account> {
+        self.require_scope("write:accounts")?;
         let url = self.route("/api/v1/accounts/update_credentials");
-        let response = self
-            .client
-            .patch(&url)
-            .headers(self.headers.clone())
-            .multipart(changes.into_form()?)
-            .send()?;
-
-        let status = response.status().clone();
-
-        if status.is_client_error() {
-            return Err(Error::Client(status));
-        } else if status.is_server_error() {
-            return Err(Error::Server(status));
-        }
+        let request = Request::patch(&url)?.multipart(changes.into_form()?);
 
-        deserialise(response)
+        self.send(request)?.json()
     }
 
     /// Post a new status to the account.
     pub fn new_status(&self, status: StatusBuilder) -> Result<Status> {
-        let response = self
-            .client
-            .post(&self.route("/api/v1/statuses"))
-            .headers(self.headers.clone())
-            .json(&status)
-            .send()?;
+        self.require_scope("write:statuses")?;
+        let request = Request::post(&self.route("/api/v1/statuses"))?.json(&status)?;
 
-        deserialise(response)
+        self.send(request)?.json()
     }
 
     /// Get the federated timeline for the instance.
@@ -602,36 +929,26 @@ impl Mastodon {
     ///
     /// # Example
     ///
-    /// ```no_run
+    /// ```
     /// # extern crate mammut;
-    /// # use mammut::{Data, Mastodon};
+    /// # use mammut::Mastodon;
     /// # use std::error::Error;
-    /// # fn main() -> Result<(), Box<Error>> {
-    /// # let data = Data {
-    /// #   base: "".into(),
-    /// #   client_id: "".into(),
-    /// #   client_secret: "".into(),
-    /// #   redirect: "".into(),
-    /// #   token: "".into(),
-    /// # };
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let data = server.data();
     /// let client = Mastodon::from_data(data);
     /// let statuses = client.statuses("user-id", None)?;
     /// # Ok(())
     /// # }
     /// ```
     ///
-    /// ```no_run
+    /// ```
     /// # extern crate mammut;
-    /// # use mammut::{Data, Mastodon, StatusesRequest};
+    /// # use mammut::{Mastodon, StatusesRequest};
     /// # use std::error::Error;
-    /// # fn main() -> Result<(), Box<Error>> {
-    /// # let data = Data {
-    /// #   base: "".into(),
-    /// #   client_id: "".into(),
-    /// #   client_secret: "".into(),
-    /// #   redirect: "".into(),
-    /// #   token: "".into(),
-    /// # };
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let data = server.data();
     /// let client = Mastodon::from_data(data);
     /// let request = StatusesRequest::default()
     ///                               .only_media();
@@ -639,39 +956,41 @@ impl Mastodon {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn statuses<'a, S>(&self, id: &str, request: S) -> Result<Page<Status>>
+    pub fn statuses<'a, S>(&self, id: &str, request: S) -> Result<Page<'_, Status>>
     where
         S: Into<Option<StatusesRequest<'a>>>,
     {
+        self.require_scope("read:statuses")?;
         let mut url = format!("{}/api/v1/accounts/{}/statuses", self.base, id);
 
         if let Some(request) = request.into() {
             url = format!("{}{}", url, request.to_querystring());
         }
 
-        let response = self.client.get(&url).headers(self.headers.clone()).send()?;
+        let response = self.send(Request::get(&url)?)?;
 
         Page::new(self, response)
     }
 
     /// Returns the client account's relationship to a list of other accounts.
     /// Such as whether they follow them or vice versa.
-    pub fn relationships(&self, ids: &[&str]) -> Result<Page<Relationship>> {
+    pub fn relationships(&self, ids: &[&str]) -> Result<Page<'_, Relationship>> {
+        self.require_scope("read:follows")?;
         let mut url = self.route("/api/v1/accounts/relationships?");
 
         if ids.len() == 1 {
             url += "id=";
-            url += &ids[0];
+            url += ids[0];
         } else {
             for id in ids {
                 url += "id[]=";
-                url += &id;
+                url += id;
                 url += "&";
             }
             url.pop();
         }
 
-        let response = self.client.get(&url).headers(self.headers.clone()).send()?;
+        let response = self.send(Request::get(&url)?)?;
 
         Page::new(self, response)
     }
@@ -684,7 +1003,8 @@ impl Mastodon {
         query: &str,
         limit: Option<u64>,
         following: bool,
-    ) -> Result<Page<Account>> {
+    ) -> Result<Page<'_, Account>> {
+        self.require_scope("read:accounts")?;
         let url = format!(
             "{}/api/v1/accounts/search?q={}&limit={}&following={}",
             self.base,
@@ -693,24 +1013,87 @@ impl Mastodon {
             following
         );
 
-        let response = self.client.get(&url).headers(self.headers.clone()).send()?;
+        let response = self.send(Request::get(&url)?)?;
 
         Page::new(self, response)
     }
 
+    /// Poll the home timeline for new statuses. See `Poll`.
+    ///
+    /// # Errors
+    /// If `access_token` is not set, or wasn't granted the `read:statuses`
+    /// scope.
+    pub fn poll_home_timeline(&self) -> Result<Poll<'_, Status>> {
+        self.require_scope("read:statuses")?;
+        Ok(Poll::new(self, "/api/v1/timelines/home", |status| {
+            &status.id
+        }))
+    }
+
+    /// Poll for new notifications. See `Poll`.
+    ///
+    /// # Errors
+    /// If `access_token` is not set, or wasn't granted the
+    /// `read:notifications` scope.
+    pub fn poll_notifications(&self) -> Result<Poll<'_, Notification>> {
+        self.require_scope("read:notifications")?;
+        Ok(Poll::new(self, "/api/v1/notifications", |notification| {
+            &notification.id
+        }))
+    }
+
+    /// Build a request to `path`, relative to the instance's base url, for
+    /// routes that don't have a method of their own.
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder<'_> {
+        RequestBuilder::new(self, method, path)
+    }
+
     methods![get, post, delete,];
 
+    // A client for the same app with another access token, keeping the
+    // transport and settings of this one.
+    fn with_token(&self, token: String, scopes: Option<Scopes>) -> Result<Self> {
+        let mut mastodon = self.clone();
+        mastodon.session = self.session.with_token(&token)?;
+        mastodon.data.token = token.into();
+        mastodon.data.scopes = scopes;
+
+        Ok(mastodon)
+    }
+
     fn route(&self, url: &str) -> String {
         let mut s = (*self.base).to_owned();
         s += url;
         s
     }
 
+    // Send the request through the transport, with the client's headers,
+    // turning a status other than `2xx` into an error.
+    fn send(&self, mut request: Request) -> Result<Response> {
+        self.session.prepare(&mut request);
+        let mut attempt = 1;
+
+        loop {
+            if let Some(wait) = self.session.wait_time() {
+                thread::sleep(wait);
+            }
+
+            trace!("REQUEST: {:?}", request);
+            let start = Instant::now();
+            let response = self.transport.send(request.clone())?;
+            log_response(&request.method, &request.url, &response, start.elapsed());
+
+            match self.session.retry_after(&request, &response, &mut attempt) {
+                Some(delay) => thread::sleep(delay),
+                None => return check_status(&request.method, &request.url, response),
+            }
+        }
+    }
+
     /// Equivalent to /api/v1/media
     pub fn media(&self, media_builder: MediaBuilder) -> Result<Attachment> {
-        use reqwest::multipart::Form;
-
-        let mut form_data = Form::new().file("file", media_builder.file.as_ref())?;
+        self.require_scope("write:media")?;
+        let mut form_data = Multipart::new().file("file", media_builder.file.as_ref())?;
 
         if let Some(description) = media_builder.description {
             form_data = form_data.text("description", description);
@@ -721,22 +1104,131 @@ impl Mastodon {
             form_data = form_data.text("focus", string);
         }
 
-        let response = self
-            .client
-            .post(&self.route("/api/v1/media"))
-            .headers(self.headers.clone())
-            .multipart(form_data)
-            .send()?;
+        let request = Request::post(&self.route("/api/v1/media"))?.multipart(form_data);
 
-        let status = response.status().clone();
+        self.send(request)?.json()
+    }
 
-        if status.is_client_error() {
-            return Err(Error::Client(status));
-        } else if status.is_server_error() {
-            return Err(Error::Server(status));
-        }
+    /// Equivalent to `/api/v1/apps/verify_credentials`, checking the access
+    /// token is still valid and returning the app it was given to.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # fn main() -> mammut::Result<()> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let mastodon = server.mastodon();
+    /// let app = mastodon.verify_app_credentials()?;
+    /// println!("Logged in with {}", app.name);
+    /// # assert!(app.vapid_key.is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    /// If `access_token` is not set.
+    pub fn verify_app_credentials(&self) -> Result<Application> {
+        self.require_token()?;
+        self.get(self.route("/api/v1/apps/verify_credentials"))
+    }
 
-        deserialise(response)
+    /// Revoke the access token, logging the user out of the app. Requests
+    /// with the token fail from then on. The request is sent to the
+    /// `revocation_endpoint` of `Data` if it has one.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # extern crate reqwest;
+    /// # extern crate serde_json;
+    /// # fn main() -> mammut::Result<()> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let mastodon = server.mastodon();
+    /// mastodon.revoke()?;
+    /// # let request = &server.requests()[0];
+    /// # assert_eq!(request.path, "/oauth/revoke");
+    /// # assert_eq!(request.form_param("token").unwrap(), mammut::testing::ACCESS_TOKEN);
+    /// # use mammut::testing::MockResponse;
+    /// # use reqwest::{Method, StatusCode};
+    /// # let mut metadata = serde_json::to_value(server.registration().discover()?)?;
+    /// # metadata["revocation_endpoint"] = format!("{}/oauth/advertised", server.base()).into();
+    /// # let path = "/.well-known/oauth-authorization-server";
+    /// # server.mock(Method::GET, path, MockResponse::json(StatusCode::OK, &metadata));
+    /// # server.mock(Method::POST, "/oauth/advertised", MockResponse::new(StatusCode::OK));
+    /// # let mut registration = server.registration();
+    /// # registration.discover()?;
+    /// # registration.register(mammut::apps::AppBuilder::default())?;
+    /// # registration.create_app_token()?.revoke()?;
+    /// # assert_eq!(server.requests().last().unwrap().path, "/oauth/advertised");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    /// If `access_token` is not set.
+    pub fn revoke(&self) -> Result<()> {
+        self.require_token()?;
+        let request = Request::post(&self.revocation_url())?.form(&[
+            ("client_id", &*self.client_id),
+            ("client_secret", &*self.client_secret),
+            ("token", &*self.token),
+        ])?;
+
+        self.send(request)?;
+        Ok(())
+    }
+
+    /// Equivalent to `POST /api/v1/accounts`, signing up a new account and
+    /// returning a client with its access token. The account can't do much
+    /// until its email address is confirmed, and the instance has approved
+    /// it if sign ups need approval.
+    ///
+    /// The client needs an app token from `Registration::create_app_token`.
+    /// See there for an example.
+    ///
+    /// # Errors
+    /// If `access_token` is not set, or wasn't granted the `write:accounts`
+    /// scope.
+    pub fn register_account(&self, signup: SignupBuilder) -> Result<Mastodon> {
+        self.require_scope("write:accounts")?;
+        let request = Request::post(&self.route("/api/v1/accounts"))?.json(&signup)?;
+        let token: AccessToken = self.send(request)?.json()?;
+
+        self.with_token(token.access_token, token.scope)
+    }
+
+    /// Receive the events of `stream` as they happen, from the streaming
+    /// API. See `EventStream` for an example.
+    ///
+    /// # Errors
+    /// If the stream is the user's own, and `access_token` is not set or
+    /// wasn't granted the `read:statuses` scope, or `read:notifications` for
+    /// `Stream::UserNotification`.
+    pub fn stream(&self, stream: Stream) -> Result<EventStream> {
+        EventStream::new(self, stream)
+    }
+
+    /// Open a WebSocket connection to the streaming API, to receive the
+    /// events of many streams at once. Requires the `websocket` feature. See
+    /// `WebSocketStream` for an example.
+    ///
+    /// # Errors
+    /// `Error::WebSocketProxy` if proxies were set on the client's
+    /// `MastodonBuilder`.
+    #[cfg(feature = "websocket")]
+    pub fn websocket(&self) -> Result<websocket::WebSocketStream> {
+        websocket::WebSocketStream::new(self)
+    }
+}
+
+impl fmt::Debug for Mastodon {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Mastodon")
+            .field("transport", &self.transport)
+            .field("headers", &redact::Headers(&self.session.headers))
+            .field("rate_limit", &self.session.rate_limit)
+            .field("wait_for_rate_limit", &self.session.wait_for_rate_limit)
+            .field("retry", &self.session.retry)
+            .field("data", &self.data)
+            .finish()
     }
 }
 
@@ -763,28 +1255,32 @@ macro_rules! from {
 
 from! {
     HttpError, Http,
+    InvalidHeaderValue, HeaderValue,
     IoError, Io,
     SerdeError, Serde,
     UrlError, Url,
+    UrlEncodedError, UrlEncoded,
 }
 
-// Convert the HTTP response body from JSON. Pass up deserialization errors
-// transparently.
-fn deserialise<T: for<'de> serde::Deserialize<'de>>(mut response: Response) -> Result<T> {
-    use std::io::Read;
-
-    let mut vec = Vec::new();
-    response.read_to_end(&mut vec)?;
-
-    match json::from_slice(&vec) {
-        Ok(t) => Ok(t),
-        // If deserializing into the desired type fails try again to
-        // see if this is an error response.
-        Err(e) => {
-            if let Ok(error) = json::from_slice(&vec) {
-                return Err(Error::Api(error));
-            }
-            Err(e.into())
-        }
+// Turn a response with a status other than `2xx` into an error.
+pub(crate) fn check_status(method: &Method, url: &Url, response: Response) -> Result<Response> {
+    if response.status.is_success() {
+        Ok(response)
+    } else {
+        let error = ResponseError::new(method.clone(), url.clone(), response);
+        Err(Error::Response(Box::new(error)))
     }
 }
+
+// Log the method, path, status and latency of a request. The query string is
+// left out as it may contain secrets.
+pub(crate) fn log_response(method: &Method, url: &Url, response: &Response, latency: Duration) {
+    debug!(
+        "method={} path={} status={} latency_ms={}",
+        method,
+        url.path(),
+        response.status.as_u16(),
+        latency.as_millis()
+    );
+    trace!("RESPONSE: {:?}", response);
+}
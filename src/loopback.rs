@@ -0,0 +1,163 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::Result;
+
+// The path the instance redirects to.
+const CALLBACK: &str = "/callback";
+// How often to check for a connection while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How long a connection has to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A short-lived HTTP listener on `127.0.0.1`, used as the redirect uri of
+/// an app so the authorisation code is captured as soon as the user
+/// authorises the app in their browser, instead of them copying it by hand.
+/// Created by `Registration::register_loopback`.
+///
+/// The listener only lives as long as the `Loopback`, and only needs to
+/// answer the one redirect.
+#[derive(Debug)]
+pub struct Loopback {
+    listener: TcpListener,
+    redirect_uri: String,
+}
+
+impl Loopback {
+    /// Listen on `127.0.0.1:port`, or a free port picked by the OS if `port`
+    /// is `0`.
+    ///
+    /// # Errors
+    /// If the port is in use.
+    pub fn bind(port: u16) -> Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+
+        Ok(Loopback {
+            listener,
+            redirect_uri: format!("http://127.0.0.1:{}{}", port, CALLBACK),
+        })
+    }
+
+    /// The uri to register as the app's redirect uri, e.g.
+    /// `http://127.0.0.1:8080/callback`.
+    pub fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+
+    /// The port the listener is on.
+    pub fn port(&self) -> u16 {
+        self.listener
+            .local_addr()
+            .map(|addr| addr.port())
+            .unwrap_or_default()
+    }
+
+    /// Wait for the browser to be redirected to the listener, returning the
+    /// full url it was redirected to. Other requests, such as for a
+    /// favicon, are answered with `404 Not Found` and otherwise ignored.
+    ///
+    /// The redirect is answered without being checked, so the page only
+    /// tells the user to return to the app. `Registration::complete_loopback`
+    /// checks it first, and tells the user if authorising failed.
+    ///
+    /// # Errors
+    /// `Error::Io` with `io::ErrorKind::TimedOut` if there's no redirect
+    /// within `timeout`.
+    pub fn wait(&self, timeout: Duration) -> Result<String> {
+        self.wait_with(timeout, |redirect| Ok(redirect.to_owned()))
+    }
+
+    // Like `wait`, passing the redirect to `check` before answering it. The
+    // browser is shown an error page if `check` fails, whose error is
+    // returned.
+    pub(crate) fn wait_with<T, F>(&self, timeout: Duration, check: F) -> Result<T>
+    where
+        F: Fn(&str) -> Result<T>,
+    {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "timed out waiting for the authorisation redirect",
+                )
+                .into());
+            }
+
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Some(result) = self.answer(stream, deadline - now, &check) {
+                        return result;
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(POLL_INTERVAL.min(deadline - now));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    // Read the request and respond to it, returning the result of `check` if
+    // it's the redirect. Connections that fail or don't send a request in
+    // time, like the speculative ones some browsers open, are dropped.
+    fn answer<T, F>(&self, stream: TcpStream, timeout: Duration, check: &F) -> Option<Result<T>>
+    where
+        F: Fn(&str) -> Result<T>,
+    {
+        stream.set_nonblocking(false).ok()?;
+        stream
+            .set_read_timeout(Some(timeout.min(REQUEST_TIMEOUT)))
+            .ok()?;
+
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).ok()?;
+
+        // Skip the headers, the browser waits for them to be read.
+        let mut line = String::new();
+        while reader.read_line(&mut line).ok()? > 2 {
+            line.clear();
+        }
+
+        let target = request_line.split_whitespace().nth(1)?.to_owned();
+        let is_redirect = target == CALLBACK || target.starts_with(&format!("{}?", CALLBACK));
+
+        let result = if is_redirect {
+            Some(check(&format!("http://127.0.0.1:{}{}", self.port(), target)))
+        } else {
+            None
+        };
+
+        let (status, body) = match result {
+            Some(Ok(_)) => (
+                "200 OK",
+                String::from("You can close this window and return to the app."),
+            ),
+            Some(Err(ref error)) => (
+                "400 Bad Request",
+                format!("The app couldn't be authorised: {}.", error),
+            ),
+            None => ("404 Not Found", String::from("Not found.")),
+        };
+
+        let mut stream = reader.into_inner();
+        let _ = write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        let _ = stream.flush();
+
+        result
+    }
+}
@@ -0,0 +1,261 @@
+// Macros shared by the routes of the blocking and async clients.
+
+// The errors section of a route's documentation, empty for `[public]` routes.
+macro_rules! errors_doc {
+    (public) => {
+        ""
+    };
+    ($scope:expr) => {
+        concat!(
+            "\n# Errors\nIf `access_token` is not set, or wasn't granted the `",
+            $scope,
+            "` scope."
+        )
+    };
+}
+
+// Check the client has an access token granted the route's scope, unless the
+// route is `[public]`.
+macro_rules! authenticate {
+    ($mastodon:ident, public) => {
+        Ok::<(), Error>(())
+    };
+    ($mastodon:ident, $scope:expr) => {
+        $mastodon.require_scope($scope)
+    };
+}
+
+// The routes of the API, with the scope each needs, expanded into the methods
+// of the blocking client with `routes!(blocking)` and of the async client with
+// `routes!(async)`.
+macro_rules! routes {
+    ($client:tt) => {
+        paged_routes! { $client;
+            (get) ["read:favourites"] favourites: "favourites" => Status,
+            (get) ["read:blocks"] blocks: "blocks" => Account,
+            (get) ["read:blocks"] domain_blocks: "domain_blocks" => String,
+            (get) ["read:follows"] follow_requests: "follow_requests" => Account,
+            (get) ["read:statuses"] get_home_timeline: "timelines/home" => Status,
+            (get) [public] get_emojis: "custom_emojis" => Emoji,
+            (get) ["read:mutes"] mutes: "mutes" => Account,
+            (get) ["read:notifications"] notifications: "notifications" => Notification,
+            (get) ["read:reports"] reports: "reports" => Report,
+        }
+
+        paged_routes_with_id! { $client;
+            (get) ["read:accounts"] followers: "accounts/{}/followers" => Account,
+            (get) ["read:accounts"] following: "accounts/{}/following" => Account,
+            (get) ["read:accounts"] reblogged_by: "statuses/{}/reblogged_by" => Account,
+            (get) ["read:accounts"] favourited_by: "statuses/{}/favourited_by" => Account,
+        }
+
+        route! { $client;
+            (delete (domain: String,)) ["write:blocks"] unblock_domain: "domain_blocks" => Empty,
+            (get) [public] instance: "instance" => Instance,
+            (get) ["read:accounts"] verify_credentials: "accounts/verify_credentials" => Account,
+            (post (account_id: &str, status_ids: Vec<&str>, comment: String,)) ["write:reports"] report: "reports" => Report,
+            (post (domain: String,)) ["write:blocks"] block_domain: "domain_blocks" => Empty,
+            (post (id: &str,)) ["write:follows"] authorize_follow_request: "accounts/follow_requests/authorize" => Empty,
+            (post (id: &str,)) ["write:follows"] reject_follow_request: "accounts/follow_requests/reject" => Empty,
+            (post (q: String, resolve: bool,)) ["read:search"] search: "search" => SearchResult,
+            (post (uri: Cow<'static, str>,)) ["write:follows"] follows: "follows" => Account,
+            (post) ["write:notifications"] clear_notifications: "notifications/clear" => Empty,
+        }
+
+        route_id! { $client;
+            (get) [public] get_account: "accounts/{}" => Account,
+            (post) ["write:follows"] follow: "accounts/{}/follow" => Account,
+            (post) ["write:follows"] unfollow: "accounts/{}/unfollow" => Account,
+            (get) ["write:blocks"] block: "accounts/{}/block" => Account,
+            (get) ["write:blocks"] unblock: "accounts/{}/unblock" => Account,
+            (get) ["write:mutes"] mute: "accounts/{}/mute" => Account,
+            (get) ["write:mutes"] unmute: "accounts/{}/unmute" => Account,
+            (get) ["read:notifications"] get_notification: "notifications/{}" => Notification,
+            (get) [public] get_status: "statuses/{}" => Status,
+            (get) [public] get_context: "statuses/{}/context" => Context,
+            (get) ["read:statuses"] get_card: "statuses/{}/card" => Card,
+            (post) ["write:statuses"] reblog: "statuses/{}/reblog" => Status,
+            (post) ["write:statuses"] unreblog: "statuses/{}/unreblog" => Status,
+            (post) ["write:favourites"] favourite: "statuses/{}/favourite" => Status,
+            (post) ["write:favourites"] unfavourite: "statuses/{}/unfavourite" => Status,
+            (delete) ["write:statuses"] delete_status: "statuses/{}" => Empty,
+        }
+    };
+}
+
+// The documentation of a route, with its errors section.
+macro_rules! route_doc {
+    ($url:expr, $auth:tt) => {
+        concat!("Equivalent to `/api/v1/", $url, "`", errors_doc!($auth))
+    };
+}
+
+macro_rules! paged_routes {
+    (blocking; ($method:ident) [$auth:tt] $name:ident: $url:expr => $ret:ty, $($rest:tt)*) => {
+        doc_comment! {
+            route_doc!($url, $auth),
+            pub fn $name(&self) -> Result<Page<'_, $ret>> {
+                authenticate!(self, $auth)?;
+                let url = self.route(concat!("/api/v1/", $url));
+                let response = self.send(Request::$method(&url)?)?;
+
+                Page::new(self, response)
+            }
+        }
+
+        paged_routes!{blocking; $($rest)*}
+    };
+
+    (async; ($method:ident) [$auth:tt] $name:ident: $url:expr => $ret:ty, $($rest:tt)*) => {
+        doc_comment! {
+            route_doc!($url, $auth),
+            pub fn $name(&self) -> impl Future<Item = Page<$ret>, Error = Error> {
+                let url = self.route(concat!("/api/v1/", $url));
+                let request = authenticate!(self, $auth)
+                    .and_then(|_| Request::$method(&url));
+
+                Page::from_request(self.clone(), request)
+            }
+        }
+
+        paged_routes!{async; $($rest)*}
+    };
+
+    ($client:tt;) => {}
+}
+
+macro_rules! paged_routes_with_id {
+    (blocking; ($method:ident) [$auth:tt] $name:ident: $url:expr => $ret:ty, $($rest:tt)*) => {
+        doc_comment! {
+            route_doc!($url, $auth),
+            pub fn $name(&self, id: &str) -> Result<Page<'_, $ret>> {
+                authenticate!(self, $auth)?;
+                let url = self.route(&format!(concat!("/api/v1/", $url), id));
+                let response = self.send(Request::$method(&url)?)?;
+
+                Page::new(self, response)
+            }
+        }
+
+        paged_routes_with_id!{blocking; $($rest)*}
+    };
+
+    (async; ($method:ident) [$auth:tt] $name:ident: $url:expr => $ret:ty, $($rest:tt)*) => {
+        doc_comment! {
+            route_doc!($url, $auth),
+            pub fn $name(&self, id: &str) -> impl Future<Item = Page<$ret>, Error = Error> {
+                let url = self.route(&format!(concat!("/api/v1/", $url), id));
+                let request = authenticate!(self, $auth)
+                    .and_then(|_| Request::$method(&url));
+
+                Page::from_request(self.clone(), request)
+            }
+        }
+
+        paged_routes_with_id!{async; $($rest)*}
+    };
+
+    ($client:tt;) => {}
+}
+
+macro_rules! route {
+    (blocking; ($method:ident ($($param:ident: $typ:ty,)*)) [$auth:tt] $name:ident: $url:expr => $ret:ty, $($rest:tt)*) => {
+        doc_comment! {
+            route_doc!($url, $auth),
+            pub fn $name(&self, $($param: $typ,)*) -> Result<$ret> {
+                authenticate!(self, $auth)?;
+
+                let form_data = json!({
+                    $(
+                        stringify!($param): $param,
+                    )*
+                });
+
+                let request = Request::$method(&self.route(concat!("/api/v1/", $url)))?
+                    .json(&form_data)?;
+                self.send(request)?.json()
+            }
+        }
+
+        route!{blocking; $($rest)*}
+    };
+
+    (blocking; ($method:ident) [$auth:tt] $name:ident: $url:expr => $ret:ty, $($rest:tt)*) => {
+        doc_comment! {
+            route_doc!($url, $auth),
+            pub fn $name(&self) -> Result<$ret> {
+                authenticate!(self, $auth)?;
+                self.$method(self.route(concat!("/api/v1/", $url)))
+            }
+        }
+
+        route!{blocking; $($rest)*}
+    };
+
+    (async; ($method:ident ($($param:ident: $typ:ty,)*)) [$auth:tt] $name:ident: $url:expr => $ret:ty, $($rest:tt)*) => {
+        doc_comment! {
+            route_doc!($url, $auth),
+            pub fn $name(&self, $($param: $typ,)*) -> impl Future<Item = $ret, Error = Error> {
+                let form_data = json!({
+                    $(
+                        stringify!($param): $param,
+                    )*
+                });
+
+                let request = authenticate!(self, $auth)
+                    .and_then(|_| Request::$method(&self.route(concat!("/api/v1/", $url))))
+                    .and_then(|request| request.json(&form_data));
+
+                self.send(request).and_then(|response| response.json())
+            }
+        }
+
+        route!{async; $($rest)*}
+    };
+
+    (async; ($method:ident) [$auth:tt] $name:ident: $url:expr => $ret:ty, $($rest:tt)*) => {
+        doc_comment! {
+            route_doc!($url, $auth),
+            pub fn $name(&self) -> impl Future<Item = $ret, Error = Error> {
+                let url = self.route(concat!("/api/v1/", $url));
+                let request = authenticate!(self, $auth)
+                    .and_then(|_| Request::$method(&url));
+
+                self.send(request).and_then(|response| response.json())
+            }
+        }
+
+        route!{async; $($rest)*}
+    };
+
+    ($client:tt;) => {}
+}
+
+macro_rules! route_id {
+    (blocking; $(($method:ident) [$auth:tt] $name:ident: $url:expr => $ret:ty,)*) => {
+        $(
+            doc_comment! {
+                route_doc!($url, $auth),
+                pub fn $name(&self, id: &str) -> Result<$ret> {
+                    authenticate!(self, $auth)?;
+                    self.$method(self.route(&format!(concat!("/api/v1/", $url), id)))
+                }
+            }
+        )*
+    };
+
+    (async; $(($method:ident) [$auth:tt] $name:ident: $url:expr => $ret:ty,)*) => {
+        $(
+            doc_comment! {
+                route_doc!($url, $auth),
+                pub fn $name(&self, id: &str) -> impl Future<Item = $ret, Error = Error> {
+                    let url = self.route(&format!(concat!("/api/v1/", $url), id));
+                    let request = authenticate!(self, $auth)
+                        .and_then(|_| Request::$method(&url));
+
+                    self.send(request).and_then(|response| response.json())
+                }
+            }
+        )*
+    };
+}
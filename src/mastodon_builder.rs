@@ -0,0 +1,267 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Proxy};
+
+use super::streaming::HEARTBEAT_TIMEOUT;
+use super::transport::{Request, Response, StreamingResponse, Transport};
+use super::{redact, Data, Error, Mastodon, Result, RetryPolicy};
+
+/// A builder pattern struct for constructing a `Mastodon` client with
+/// options beyond the defaults of `Mastodon::from_data`.
+///
+/// ```
+/// # extern crate mammut;
+/// # fn main() -> mammut::Result<()> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let data = server.data();
+/// use std::time::Duration;
+/// use mammut::MastodonBuilder;
+///
+/// let mastodon = MastodonBuilder::new(data)
+///     .timeout(Duration::from_secs(30))
+///     .connect_timeout(Duration::from_secs(5))
+///     .user_agent("my-bot/1.0")
+///     .build()?;
+///
+/// mastodon.verify_credentials()?;
+/// # mastodon.stream(mammut::streaming::Stream::Public)?.next();
+/// # assert_eq!(server.requests().len(), 2);
+/// # for request in server.requests() {
+/// #     assert_eq!(request.headers["user-agent"], "my-bot/1.0");
+/// # }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// To have many clients share one connection pool, build a
+/// `reqwest::Client` once and give each builder a clone of it. A shared
+/// client keeps its own timeouts and proxies, so those options can't be set
+/// on the builder as well. Its timeout also applies to the streams of
+/// `Mastodon::stream`, which go quiet for up to 15 seconds between
+/// heartbeats.
+///
+/// ```
+/// # extern crate mammut;
+/// # extern crate reqwest;
+/// # fn main() -> mammut::Result<()> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let accounts = vec![server.data(), server.data()];
+/// use std::time::Duration;
+/// use mammut::MastodonBuilder;
+///
+/// let client = reqwest::Client::builder()
+///     .timeout(Duration::from_secs(30))
+///     .build()?;
+///
+/// let clients = accounts
+///     .into_iter()
+///     .map(|data| MastodonBuilder::new(data).client(client.clone()).build())
+///     .collect::<mammut::Result<Vec<_>>>()?;
+/// # assert_eq!(clients.len(), 2);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct MastodonBuilder {
+    data: Data,
+    client: Option<Client>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    proxies: Vec<Proxy>,
+    user_agent: Option<Cow<'static, str>>,
+    headers: HeaderMap,
+    wait_for_rate_limit: bool,
+    retry: RetryPolicy,
+}
+
+impl fmt::Debug for MastodonBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MastodonBuilder")
+            .field("data", &self.data)
+            .field("client", &self.client)
+            .field("timeout", &self.timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            // Proxies may have credentials.
+            .field("proxies", &format_args!("{} proxies", self.proxies.len()))
+            .field("user_agent", &self.user_agent)
+            .field("headers", &redact::Headers(&self.headers))
+            .field("wait_for_rate_limit", &self.wait_for_rate_limit)
+            .field("retry", &self.retry)
+            .finish()
+    }
+}
+
+impl MastodonBuilder {
+    /// Create a new builder for the client of the app and user in `data`.
+    pub fn new(data: Data) -> Self {
+        MastodonBuilder {
+            data,
+            client: None,
+            timeout: None,
+            connect_timeout: None,
+            proxies: Vec::new(),
+            user_agent: None,
+            headers: HeaderMap::new(),
+            wait_for_rate_limit: false,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Send requests through `client`, sharing its connection pool with
+    /// every other user of the client.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Set the timeout for sending each request and for each read of its
+    /// response. It doesn't apply to the streams of `Mastodon::stream`, which
+    /// go quiet between heartbeats, and reconnect when they miss one instead.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout for connecting to the instance.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Send requests through a proxy. HTTP and HTTPS proxies are always
+    /// supported, SOCKS5 proxies (`socks5://` and `socks5h://`) need the
+    /// `socks` feature. WebSocket connections can't be sent through a proxy,
+    /// so `Mastodon::websocket` fails on a client with one.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxies.push(proxy);
+        self
+    }
+
+    /// Set the `User-Agent` header sent with each request.
+    pub fn user_agent<I: Into<Cow<'static, str>>>(mut self, user_agent: I) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Add a header to send with each request.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Whether to wait for the rate limit to reset, see
+    /// `Mastodon::wait_for_rate_limit`.
+    pub fn wait_for_rate_limit(mut self, wait: bool) -> Self {
+        self.wait_for_rate_limit = wait;
+        self
+    }
+
+    /// How to retry failed requests, see `Mastodon::retry_policy`.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Build the client.
+    ///
+    /// # Errors
+    /// If the access token or user agent isn't a valid header value, if the
+    /// HTTP client can't be built, or if timeouts or proxies were set along
+    /// with a shared client.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # let server = mammut::testing::MockServer::start();
+    /// use mammut::{Error, MastodonBuilder};
+    ///
+    /// let mut data = server.data();
+    /// data.token = "corrupted\n".into();
+    ///
+    /// match MastodonBuilder::new(data).build() {
+    ///     Err(Error::HeaderValue(_)) => {}
+    ///     other => panic!("{:?}", other),
+    /// }
+    /// ```
+    pub fn build(self) -> Result<Mastodon> {
+        let has_client_options =
+            self.timeout.is_some() || self.connect_timeout.is_some() || !self.proxies.is_empty();
+        #[cfg(feature = "websocket")]
+        let connection = crate::websocket::Connection {
+            timeout: self.timeout,
+            connect_timeout: self.connect_timeout,
+            proxied: !self.proxies.is_empty(),
+        };
+
+        let transport: Arc<dyn Transport> = match self.client {
+            Some(_) if has_client_options => return Err(Error::SharedClientConflict),
+            Some(client) => Arc::new(client),
+            None => {
+                let mut builder = Client::builder();
+                let mut streaming = Client::builder().timeout(HEARTBEAT_TIMEOUT);
+
+                if let Some(timeout) = self.timeout {
+                    builder = builder.timeout(timeout);
+                }
+
+                if let Some(timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                    streaming = streaming.connect_timeout(timeout);
+                }
+
+                for proxy in self.proxies {
+                    builder = builder.proxy(proxy.clone());
+                    streaming = streaming.proxy(proxy);
+                }
+
+                Arc::new(BuiltTransport {
+                    client: builder.build()?,
+                    streaming: streaming.build()?,
+                })
+            }
+        };
+
+        let mut mastodon = Mastodon::from_data_with_shared_transport(self.data, transport)?
+            .wait_for_rate_limit(self.wait_for_rate_limit)
+            .retry_policy(self.retry);
+
+        #[cfg(feature = "websocket")]
+        {
+            mastodon.connection = connection;
+        }
+
+        if let Some(user_agent) = self.user_agent {
+            let value = HeaderValue::from_str(&user_agent)?;
+            mastodon.session.headers.insert(header::USER_AGENT, value);
+        }
+
+        for (name, value) in &self.headers {
+            mastodon.session.headers.insert(name.clone(), value.clone());
+        }
+
+        Ok(mastodon)
+    }
+}
+
+// The transport of a client made by the builder, which sends streaming
+// requests through a client of their own. Its reads time out after the
+// heartbeat timeout rather than the builder's timeout, so that the thread
+// reading a connection the stream has given up on stops.
+#[derive(Debug)]
+struct BuiltTransport {
+    client: Client,
+    streaming: Client,
+}
+
+impl Transport for BuiltTransport {
+    fn send(&self, request: Request) -> Result<Response> {
+        self.client.send(request)
+    }
+
+    fn send_streaming(&self, request: Request) -> Result<StreamingResponse> {
+        self.streaming.send_streaming(request)
+    }
+}
@@ -1,51 +1,95 @@
+use std::convert::TryFrom;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::time::Duration;
+use std::{fmt, mem, thread, vec};
+
+use chrono::{DateTime, Utc};
 use hyperx::header::{Header, Link, RelationType};
-use reqwest::header::LINK;
-use reqwest::Response;
+use reqwest::header::{HeaderMap, LINK};
+use reqwest::Method;
 use serde::Deserialize;
 use url::Url;
 
-use super::{deserialise, Mastodon, Result};
+use super::{Error, Mastodon, Result};
+use crate::entities::account::Account;
 use crate::entities::itemsiter::ItemsIter;
+use crate::entities::notification::Notification;
+use crate::entities::status::Status;
+use crate::redact;
+use crate::transport::{Request, Response};
 
+/// A page of entities returned from a paginated route, along with the links to
+/// the pages before and after it.
 pub struct Page<'a, T: for<'de> Deserialize<'de>> {
     mastodon: &'a Mastodon,
     next: Option<Url>,
     prev: Option<Url>,
+    bounds: Bounds<T>,
     /// Initial set of items
     pub initial_items: Vec<T>,
 }
 
+// Where the item iterators of a `Page` stop, and how they fetch pages.
+struct Bounds<T> {
+    max_items: Option<usize>,
+    max_pages: Option<usize>,
+    not_before: Option<(DateTime<Utc>, CreatedAtFn<T>)>,
+    limit: Option<u32>,
+    delay: Option<Duration>,
+}
+
+type CreatedAtFn<T> = fn(&T) -> DateTime<Utc>;
+
+impl<T> Default for Bounds<T> {
+    fn default() -> Self {
+        Bounds {
+            max_items: None,
+            max_pages: None,
+            not_before: None,
+            limit: None,
+            delay: None,
+        }
+    }
+}
+
 macro_rules! pages {
     ($($direction:ident: $fun:ident),*) => {
 
         $(
+            doc_comment! {
+                concat!("Fetches the ", stringify!($direction), " page of items, returning `None` if there is none."),
             pub fn $fun(&mut self) -> Result<Option<Vec<T>>> {
-                let url = match self.$direction.take() {
-                    Some(s) => s,
+                let url = match self.$direction {
+                    Some(ref url) => with_limit(url, self.bounds.limit),
                     None => return Ok(None),
                 };
 
-                let response = self.mastodon.client.get(url)
-                    .headers(self.mastodon.headers.clone())
-                    .send()?;
+                // The links are only replaced once the page has been read, so
+                // it can be fetched again after an error.
+                let response = self.mastodon.send(Request::get(url.as_str())?)?;
+                let (prev, next) = get_links(&response.headers)?;
+                let items = response.json()?;
 
-                let (prev, next) = get_links(&response)?;
                 self.next = next;
                 self.prev = prev;
 
-                deserialise(response)
+                Ok(Some(items))
+            }
             }
          )*
     }
 }
 
 impl<'a, T: for<'de> Deserialize<'de>> Page<'a, T> {
+    /// Construct a page from a response, parsing the `Link` header for the
+    /// surrounding pages.
     pub fn new(mastodon: &'a Mastodon, response: Response) -> Result<Self> {
-        let (prev, next) = get_links(&response)?;
+        let (prev, next) = get_links(&response.headers)?;
         Ok(Page {
-            initial_items: deserialise(response)?,
+            initial_items: response.json()?,
             next,
             prev,
+            bounds: Bounds::default(),
             mastodon,
         })
     }
@@ -54,9 +98,98 @@ impl<'a, T: for<'de> Deserialize<'de>> Page<'a, T> {
         next: next_page,
         prev: prev_page
     }
-}
 
-impl<'a, T: Clone + for<'de> Deserialize<'de>> Page<'a, T> {
+    /// The position of this page, for fetching the pages around it later,
+    /// even with another `Mastodon`.
+    pub fn cursor(&self) -> Cursor {
+        Cursor::new(self.next.clone(), self.prev.clone())
+    }
+
+    /// Continue from a saved `Cursor`. The page has no items of its own,
+    /// `next_page`, `prev_page` and the item iterators fetch the pages after
+    /// and before the one the cursor was taken from.
+    pub fn from_cursor(mastodon: &'a Mastodon, cursor: Cursor) -> Self {
+        let (next, prev) = cursor.into_links();
+        Page {
+            mastodon,
+            next,
+            prev,
+            bounds: Bounds::default(),
+            initial_items: Vec::new(),
+        }
+    }
+
+    /// Stop the item iterators after `max` items.
+    ///
+    /// These bounds let a bot sample a long timeline or a big account's
+    /// followers without walking every page:
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # extern crate chrono;
+    /// # fn main() -> mammut::Result<()> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let mastodon = server.mastodon();
+    /// use std::time::Duration;
+    /// use chrono::{DateTime, Utc};
+    ///
+    /// let statuses: Vec<_> = mastodon
+    ///     .get_home_timeline()?
+    ///     .max_items(100)
+    ///     .max_pages(5)
+    ///     .not_before("2019-01-02T00:00:00Z".parse::<DateTime<Utc>>().unwrap())
+    ///     .next_pages_limit(1)
+    ///     .page_delay(Duration::from_millis(10))
+    ///     .items_iter()
+    ///     .collect();
+    ///
+    /// // The status from the 1st of January is older than the cutoff.
+    /// assert_eq!(statuses.len(), 2);
+    /// # assert_eq!(server.requests().last().unwrap().query_param("limit").as_deref(), Some("1"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn max_items(mut self, max: usize) -> Self {
+        self.bounds.max_items = Some(max);
+        self
+    }
+
+    /// Stop the item iterators after the items of `max` pages, counting this
+    /// one if it has any items.
+    pub fn max_pages(mut self, max: usize) -> Self {
+        self.bounds.max_pages = Some(max);
+        self
+    }
+
+    /// Stop the item iterators at the first item created before `cutoff`.
+    /// Iterating backward, with `prev_items_iter`, the older items are
+    /// skipped instead.
+    pub fn not_before(mut self, cutoff: DateTime<Utc>) -> Self
+    where
+        T: CreatedAt,
+    {
+        self.bounds.not_before = Some((cutoff, T::created_at));
+        self
+    }
+
+    /// Fetch the following pages with `limit` items each, instead of the
+    /// route's default. Mastodon allows at most 40 for most routes, and 80
+    /// for accounts.
+    ///
+    /// This page has already been fetched, so it keeps the size the route
+    /// asked for. Routes with a `limit` option, like `StatusesRequest::limit`
+    /// or the `limit` of `search_accounts`, set it for the first page too.
+    pub fn next_pages_limit(mut self, limit: u32) -> Self {
+        self.bounds.limit = Some(limit);
+        self
+    }
+
+    /// Wait for `delay` before the item iterators fetch each page.
+    pub fn page_delay(mut self, delay: Duration) -> Self {
+        self.bounds.delay = Some(delay);
+        self
+    }
+
     /// Returns an iterator that provides a stream of `T`s
     ///
     /// This abstracts away the process of iterating over each item in a page, then making an http
@@ -64,20 +197,18 @@ impl<'a, T: Clone + for<'de> Deserialize<'de>> Page<'a, T> {
     /// `T`s, calling `self.next_page()` when necessary to get more of them, until there are no more
     /// items.
     ///
+    /// The iterator ends at the first error fetching a page, use
+    /// `try_items_iter` to find out about it.
+    ///
     /// # Example
     ///
-    /// ```no_run
+    /// ```
     /// # extern crate mammut;
     /// # use std::error::Error;
-    /// use mammut::{Mastodon, Data, StatusesRequest};
-    /// # fn main() -> Result<(), Box<Error>> {
-    /// #   let data = Data {
-    /// #       base: "".into(),
-    /// #       client_id: "".into(),
-    /// #       client_secret: "".into(),
-    /// #       redirect: "".into(),
-    /// #       token: "".into(),
-    /// #   };
+    /// use mammut::{Mastodon, StatusesRequest};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #   let server = mammut::testing::MockServer::start();
+    /// #   let data = server.data();
     /// let mastodon = Mastodon::from_data(data);
     /// let req = StatusesRequest::new();
     /// let resp = mastodon.statuses("some-id", req)?;
@@ -91,15 +222,682 @@ impl<'a, T: Clone + for<'de> Deserialize<'de>> Page<'a, T> {
     where
         T: 'a,
     {
-        ItemsIter::new(self)
+        ItemsIter::new(self.try_items_iter())
+    }
+
+    /// Returns an iterator over every `T` like `items_iter`, which returns an
+    /// error if a page couldn't be fetched instead of ending. Calling `next`
+    /// again after an error tries to fetch the same page again.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # extern crate reqwest;
+    /// # fn main() -> mammut::Result<()> {
+    /// # use mammut::testing::MockResponse;
+    /// # use reqwest::{Method, StatusCode};
+    /// # let server = mammut::testing::MockServer::start();
+    /// use mammut::{Mastodon, RetryPolicy};
+    ///
+    /// let mastodon = Mastodon::from_data(server.data()).retry_policy(RetryPolicy::never());
+    /// let mut statuses = mastodon.get_home_timeline()?.try_items_iter();
+    /// # server.mock_once(Method::GET, "/api/v1/timelines/home",
+    /// #     MockResponse::new(StatusCode::BAD_GATEWAY));
+    ///
+    /// assert_eq!(statuses.next().unwrap()?.id, "3");
+    /// assert_eq!(statuses.next().unwrap()?.id, "2");
+    /// // Fetching the second page fails with `502 Bad Gateway`...
+    /// assert!(statuses.next().unwrap().is_err());
+    /// // ...and is tried again.
+    /// assert_eq!(statuses.next().unwrap()?.id, "1");
+    /// assert!(statuses.next().is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_items_iter(self) -> TryItemsIter<'a, T> {
+        TryItemsIter::new(self, false)
+    }
+
+    /// Returns an iterator over every `T` in this page and the pages before
+    /// it, following the `prev` links towards newer items. The items are
+    /// returned oldest first. Ends at the first error, like `items_iter`.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # extern crate reqwest;
+    /// # fn main() -> mammut::Result<()> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let mastodon = server.mastodon();
+    /// use mammut::entities::status::Status;
+    /// use reqwest::Method;
+    ///
+    /// let oldest = mastodon
+    ///     .request(Method::GET, "/api/v1/timelines/home")
+    ///     .query(&[("max_id", "2")])
+    ///     .paged::<Status>()?;
+    ///
+    /// let ids: Vec<String> = oldest.prev_items_iter().map(|status| status.id).collect();
+    /// assert_eq!(ids, ["1", "2", "3"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prev_items_iter(self) -> impl Iterator<Item = T> + 'a
+    where
+        T: 'a,
+    {
+        ItemsIter::new(self.try_prev_items_iter())
+    }
+
+    /// Returns an iterator over every `T` like `prev_items_iter`, which
+    /// returns an error if a page couldn't be fetched instead of ending,
+    /// like `try_items_iter`.
+    pub fn try_prev_items_iter(self) -> TryItemsIter<'a, T> {
+        TryItemsIter::new(self, true)
+    }
+
+    /// Returns an iterator over every `T` like `try_items_iter`, which
+    /// fetches the following pages on a worker thread while the current one
+    /// is being consumed, so iterating doesn't stall at each page.
+    ///
+    /// Up to `depth` pages are fetched ahead, at least one. The worker stops
+    /// when the iterator is dropped, and after an error, which is returned
+    /// once the pages fetched before it have been consumed. Calling `next`
+    /// again starts a new worker that tries the same page again.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # fn main() -> mammut::Result<()> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let mastodon = server.mastodon();
+    /// let ids = mastodon
+    ///     .get_home_timeline()?
+    ///     .next_pages_limit(1)
+    ///     .prefetch(4)
+    ///     .map(|status| status.map(|status| status.id))
+    ///     .collect::<mammut::Result<Vec<String>>>()?;
+    ///
+    /// assert_eq!(ids, ["3", "2", "1"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prefetch(self, depth: usize) -> Prefetch<T>
+    where
+        T: Send + 'static,
+    {
+        Prefetch::new(self, depth)
+    }
+}
+
+/// The position of a `Page`: the links to the pages after and before it.
+///
+/// Unlike a `Page` it doesn't borrow the client, and can be serialised, so a
+/// long crawl can save its progress and continue from it after a restart.
+///
+/// ```
+/// # extern crate mammut;
+/// # extern crate serde_json;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let mastodon = server.mastodon();
+/// use mammut::page::{Cursor, Page};
+/// use mammut::entities::status::Status;
+///
+/// let page = mastodon.get_home_timeline()?;
+/// // Handle `page.initial_items`, then save where the crawl is up to.
+/// let cursor = page.cursor();
+/// assert_eq!(cursor.max_id().as_deref(), Some("2"));
+/// let saved = serde_json::to_string(&cursor)?;
+///
+/// // Later, maybe in another process:
+/// let cursor: Cursor = serde_json::from_str(&saved)?;
+/// let rest: Vec<Status> = Page::from_cursor(&mastodon, cursor).items_iter().collect();
+/// assert_eq!(rest.len(), 1);
+/// assert_eq!(rest[0].id, "1");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawCursor", into = "RawCursor")]
+pub struct Cursor {
+    next: Option<Url>,
+    prev: Option<Url>,
+}
+
+// A `Cursor` as it's serialised, with the links as strings.
+#[derive(Clone, Serialize, Deserialize)]
+struct RawCursor {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    next: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prev: Option<String>,
+}
+
+impl Cursor {
+    pub(crate) fn new(next: Option<Url>, prev: Option<Url>) -> Self {
+        Cursor { next, prev }
+    }
+
+    pub(crate) fn into_links(self) -> (Option<Url>, Option<Url>) {
+        (self.next, self.prev)
+    }
+
+    /// The link to the page after this one, if there is one.
+    pub fn next_url(&self) -> Option<&str> {
+        self.next.as_ref().map(Url::as_str)
+    }
+
+    /// The link to the page before this one, if there is one.
+    pub fn prev_url(&self) -> Option<&str> {
+        self.prev.as_ref().map(Url::as_str)
+    }
+
+    /// The `max_id` the page after this one is fetched with, i.e. the id of
+    /// the oldest item of this page.
+    pub fn max_id(&self) -> Option<String> {
+        query_param(self.next.as_ref()?, &["max_id"])
+    }
+
+    /// The `min_id` or `since_id` the page before this one is fetched with,
+    /// i.e. the id of the newest item of this page.
+    pub fn min_id(&self) -> Option<String> {
+        query_param(self.prev.as_ref()?, &["min_id", "since_id"])
+    }
+
+    /// Fetch the page after this one, or `None` if there is none.
+    pub fn next_page<'a, T>(&self, mastodon: &'a Mastodon) -> Result<Option<Page<'a, T>>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        fetch(mastodon, self.next.as_ref())
+    }
+
+    /// Fetch the page before this one, or `None` if there is none.
+    pub fn prev_page<'a, T>(&self, mastodon: &'a Mastodon) -> Result<Option<Page<'a, T>>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        fetch(mastodon, self.prev.as_ref())
+    }
+}
+
+impl fmt::Debug for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cursor")
+            .field("next", &self.next.as_ref().map(redact::Link))
+            .field("prev", &self.prev.as_ref().map(redact::Link))
+            .finish()
+    }
+}
+
+impl TryFrom<RawCursor> for Cursor {
+    type Error = url::ParseError;
+
+    fn try_from(raw: RawCursor) -> std::result::Result<Self, Self::Error> {
+        Ok(Cursor {
+            next: raw.next.as_deref().map(Url::parse).transpose()?,
+            prev: raw.prev.as_deref().map(Url::parse).transpose()?,
+        })
+    }
+}
+
+impl From<Cursor> for RawCursor {
+    fn from(cursor: Cursor) -> Self {
+        RawCursor {
+            next: cursor.next.map(Url::into_string),
+            prev: cursor.prev.map(Url::into_string),
+        }
+    }
+}
+
+fn fetch<'a, T>(mastodon: &'a Mastodon, url: Option<&Url>) -> Result<Option<Page<'a, T>>>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    match url {
+        Some(url) => {
+            let response = mastodon.send(Request::get(url.as_str())?)?;
+            Page::new(mastodon, response).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+// The value of the first of `names` in the query of `url`.
+fn query_param(url: &Url, names: &[&str]) -> Option<String> {
+    names.iter().find_map(|name| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    })
+}
+
+/// An iterator over the items of a `Page` and the pages after or before it,
+/// returned by `Page::try_items_iter` and `Page::try_prev_items_iter`.
+///
+/// It ends at the bounds set on the page, with `Page::max_items`,
+/// `Page::max_pages` and `Page::not_before`.
+pub struct TryItemsIter<'a, T: for<'de> Deserialize<'de>> {
+    page: Page<'a, T>,
+    buffer: vec::IntoIter<T>,
+    backward: bool,
+    items: usize,
+    pages: usize,
+    done: bool,
+}
+
+impl<'a, T: for<'de> Deserialize<'de>> TryItemsIter<'a, T> {
+    fn new(mut page: Page<'a, T>, backward: bool) -> Self {
+        let mut items = mem::take(&mut page.initial_items);
+        if backward {
+            items.reverse();
+        }
+
+        TryItemsIter {
+            page,
+            pages: if items.is_empty() { 0 } else { 1 },
+            buffer: items.into_iter(),
+            backward,
+            items: 0,
+            done: false,
+        }
+    }
+
+    // The next item, fetching pages until one has any.
+    fn next_item(&mut self) -> Option<Result<T>> {
+        loop {
+            if let Some(item) = self.buffer.next() {
+                return Some(Ok(item));
+            }
+
+            if self
+                .page
+                .bounds
+                .max_pages
+                .map_or(false, |max| self.pages >= max)
+            {
+                return None;
+            }
+
+            if let Some(delay) = self.page.bounds.delay.filter(|_| self.pages > 0) {
+                thread::sleep(delay);
+            }
+
+            let page = if self.backward {
+                self.page.prev_page()
+            } else {
+                self.page.next_page()
+            };
+
+            match page {
+                Ok(Some(mut items)) => {
+                    if items.is_empty() {
+                        return None;
+                    }
+                    // Pages are newest first, so going backward each is reversed.
+                    if self.backward {
+                        items.reverse();
+                    }
+                    self.pages += 1;
+                    self.buffer = items.into_iter();
+                }
+                Ok(None) => return None,
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+impl<'a, T: for<'de> Deserialize<'de>> Iterator for TryItemsIter<'a, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done
+            || self
+                .page
+                .bounds
+                .max_items
+                .map_or(false, |max| self.items >= max)
+        {
+            return None;
+        }
+
+        loop {
+            let item = match self.next_item()? {
+                Ok(item) => item,
+                Err(error) => return Some(Err(error)),
+            };
+
+            if let Some((cutoff, created_at)) = self.page.bounds.not_before {
+                if created_at(&item) < cutoff {
+                    if self.backward {
+                        continue;
+                    }
+                    self.done = true;
+                    return None;
+                }
+            }
+
+            self.items += 1;
+            return Some(Ok(item));
+        }
+    }
+}
+
+/// An iterator over the items of a `Page` and the pages after it, which
+/// fetches the pages ahead on a worker thread. Returned by `Page::prefetch`.
+pub struct Prefetch<T> {
+    mastodon: Mastodon,
+    bounds: Bounds<T>,
+    depth: usize,
+    buffer: vec::IntoIter<T>,
+    receiver: Option<Receiver<Fetched<T>>>,
+    retry: Option<Url>,
+    items: usize,
+    pages: usize,
+    done: bool,
+}
+
+// A page fetched by the worker of a `Prefetch`, or the error fetching the
+// page at the url.
+type Fetched<T> = std::result::Result<Vec<T>, (Error, Url)>;
+
+impl<T: for<'de> Deserialize<'de> + Send + 'static> Prefetch<T> {
+    fn new(mut page: Page<T>, depth: usize) -> Self {
+        let items = mem::take(&mut page.initial_items);
+        let mut prefetch = Prefetch {
+            mastodon: page.mastodon.clone(),
+            bounds: mem::take(&mut page.bounds),
+            depth: depth.max(1),
+            pages: if items.is_empty() { 0 } else { 1 },
+            buffer: items.into_iter(),
+            receiver: None,
+            retry: None,
+            items: 0,
+            done: false,
+        };
+
+        if let Some(url) = page.next.take() {
+            prefetch.spawn(url);
+        }
+
+        prefetch
+    }
+
+    // Start a worker fetching the pages from `url` onwards.
+    fn spawn(&mut self, url: Url) {
+        // The channel holds `depth - 1` pages, and the worker one more while
+        // it waits to send it.
+        let (sender, receiver) = mpsc::sync_channel(self.depth - 1);
+        let mastodon = self.mastodon.clone();
+        let remaining = self
+            .bounds
+            .max_pages
+            .map(|max| max.saturating_sub(self.pages));
+        let limit = self.bounds.limit;
+        let delay = self.bounds.delay.filter(|_| self.pages > 0);
+
+        thread::spawn(move || prefetch(&mastodon, url, remaining, limit, delay, &sender));
+        self.receiver = Some(receiver);
+    }
+}
+
+// The worker of a `Prefetch`, sending the pages from `url` onwards until
+// there are no more, `remaining` have been sent, or the iterator is dropped.
+fn prefetch<T: for<'de> Deserialize<'de>>(
+    mastodon: &Mastodon,
+    url: Url,
+    mut remaining: Option<usize>,
+    limit: Option<u32>,
+    delay: Option<Duration>,
+    sender: &SyncSender<Fetched<T>>,
+) {
+    let mut next = Some(url);
+    let mut fetched = false;
+
+    while let Some(url) = next.take() {
+        if remaining == Some(0) {
+            return;
+        }
+        if let Some(delay) = delay.filter(|_| fetched) {
+            thread::sleep(delay);
+        }
+        fetched = true;
+
+        let page = fetch::<T>(mastodon, Some(&with_limit(&url, limit)));
+        let items = match page {
+            Ok(Some(mut page)) => {
+                next = page.next.take();
+                mem::take(&mut page.initial_items)
+            }
+            Ok(None) => return,
+            Err(error) => {
+                let _ = sender.send(Err((error, url)));
+                return;
+            }
+        };
+
+        if items.is_empty() || sender.send(Ok(items)).is_err() {
+            return;
+        }
+        remaining = remaining.map(|remaining| remaining - 1);
     }
 }
 
-fn get_links(response: &Response) -> Result<(Option<Url>, Option<Url>)> {
+impl<T: for<'de> Deserialize<'de> + Send + 'static> Iterator for Prefetch<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.bounds.max_items.map_or(false, |max| self.items >= max) {
+            return None;
+        }
+
+        loop {
+            if let Some(item) = self.buffer.next() {
+                if let Some((cutoff, created_at)) = self.bounds.not_before {
+                    if created_at(&item) < cutoff {
+                        self.done = true;
+                        return None;
+                    }
+                }
+
+                self.items += 1;
+                return Some(Ok(item));
+            }
+
+            if self.receiver.is_none() {
+                let url = self.retry.take()?;
+                self.spawn(url);
+            }
+
+            match self.receiver.as_ref()?.recv() {
+                Ok(Ok(items)) => {
+                    self.pages += 1;
+                    self.buffer = items.into_iter();
+                }
+                Ok(Err((error, url))) => {
+                    self.receiver = None;
+                    self.retry = Some(url);
+                    return Some(Err(error));
+                }
+                // The worker has run out of pages.
+                Err(_) => {
+                    self.receiver = None;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// An entity with a creation date, which `Page::not_before` compares against
+/// its cutoff.
+pub trait CreatedAt {
+    /// When the entity was created.
+    fn created_at(&self) -> DateTime<Utc>;
+}
+
+impl CreatedAt for Status {
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+impl CreatedAt for Notification {
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+impl CreatedAt for Account {
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+/// Polls a timeline for items newer than the newest one seen so far, using
+/// `min_id`. Created with `Mastodon::poll_home_timeline` and
+/// `Mastodon::poll_notifications`.
+///
+/// `poll` fetches everything new since the last call, oldest first. The
+/// first call fetches the latest page, unless a starting point was set with
+/// `since`. As an iterator, a `Poll` returns the new items one at a time and
+/// waits for the interval between polls when there are none, forever.
+///
+/// ```
+/// # extern crate mammut;
+/// # fn main() -> mammut::Result<()> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let mastodon = server.mastodon();
+/// let mut poll = mastodon.poll_home_timeline()?.since("1");
+///
+/// let ids: Vec<String> = poll.poll()?.into_iter().map(|status| status.id).collect();
+/// assert_eq!(ids, ["2", "3"]);
+/// assert_eq!(poll.last_id(), Some("3"));
+///
+/// // Nothing new has been posted since.
+/// assert!(poll.poll()?.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+pub struct Poll<'a, T: for<'de> Deserialize<'de>> {
+    mastodon: &'a Mastodon,
+    path: &'static str,
+    id: fn(&T) -> &str,
+    last_id: Option<String>,
+    interval: Duration,
+    buffer: vec::IntoIter<T>,
+    polled: bool,
+}
+
+impl<'a, T: for<'de> Deserialize<'de>> Poll<'a, T> {
+    pub(crate) fn new(mastodon: &'a Mastodon, path: &'static str, id: fn(&T) -> &str) -> Self {
+        Poll {
+            mastodon,
+            path,
+            id,
+            last_id: None,
+            interval: Duration::from_secs(60),
+            buffer: Vec::new().into_iter(),
+            polled: false,
+        }
+    }
+
+    /// Only fetch items newer than the one with `id`, e.g. the `last_id` of
+    /// an earlier poll.
+    pub fn since<I: Into<String>>(mut self, id: I) -> Self {
+        self.last_id = Some(id.into());
+        self
+    }
+
+    /// Set how long the iterator waits between polls that found nothing new.
+    /// Defaults to a minute.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The id of the newest item fetched so far.
+    pub fn last_id(&self) -> Option<&str> {
+        self.last_id.as_deref()
+    }
+
+    /// Fetch every item newer than the last one fetched, oldest first.
+    pub fn poll(&mut self) -> Result<Vec<T>> {
+        let mut request = self
+            .mastodon
+            .request(Method::GET, self.path)
+            .query(&[("limit", "40")]);
+
+        let items = match self.last_id {
+            Some(ref min_id) => {
+                request = request.query(&[("min_id", min_id)]);
+                request
+                    .paged()?
+                    .try_prev_items_iter()
+                    .collect::<Result<_>>()?
+            }
+            None => {
+                let mut items = request.paged::<T>()?.initial_items;
+                items.reverse();
+                items
+            }
+        };
+
+        if let Some(newest) = items.last() {
+            self.last_id = Some((self.id)(newest).to_owned());
+        }
+
+        Ok(items)
+    }
+}
+
+impl<'a, T: for<'de> Deserialize<'de>> Iterator for Poll<'a, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.buffer.next() {
+                return Some(Ok(item));
+            }
+
+            if self.polled {
+                thread::sleep(self.interval);
+            }
+            self.polled = true;
+
+            match self.poll() {
+                Ok(items) => self.buffer = items.into_iter(),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+// `url` with its `limit` query parameter replaced by `limit`, if there is one.
+fn with_limit(url: &Url, limit: Option<u32>) -> Url {
+    let limit = match limit {
+        Some(limit) => limit.to_string(),
+        None => return url.clone(),
+    };
+
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "limit")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    let mut url = url.clone();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair("limit", &limit);
+    url
+}
+
+pub(crate) fn get_links(headers: &HeaderMap) -> Result<(Option<Url>, Option<Url>)> {
     let mut prev = None;
     let mut next = None;
 
-    let link_header = response.headers().get_all(LINK);
+    let link_header = headers.get_all(LINK);
     for value in &link_header {
         let parsed: Link = Header::parse_header(&value)?;
         for value in parsed.values() {
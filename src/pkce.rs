@@ -0,0 +1,90 @@
+use std::fmt;
+
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use crate::redact;
+
+/// A PKCE code verifier and its `S256` challenge, as described in
+/// [RFC 7636](https://tools.ietf.org/html/rfc7636).
+///
+/// The challenge is sent with the authorisation url, and the verifier with
+/// the request for the access token, so an intercepted authorisation code
+/// can't be exchanged for a token by anyone else. `Registration` does this
+/// for you, `Pkce` is only needed to handle it yourself.
+///
+/// ```
+/// use mammut::pkce::Pkce;
+///
+/// let pkce = Pkce::new();
+/// assert_eq!(pkce.method(), "S256");
+/// assert!(pkce.verify(&pkce.challenge()));
+///
+/// let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk");
+/// assert_eq!(pkce.challenge(), "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+/// ```
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Pkce {
+    verifier: String,
+}
+
+impl Pkce {
+    /// Generate a new random code verifier.
+    pub fn new() -> Self {
+        Pkce {
+            verifier: random_token(),
+        }
+    }
+
+    /// Use an existing code verifier, e.g. one saved between requests.
+    pub fn from_verifier<I: Into<String>>(verifier: I) -> Self {
+        Pkce {
+            verifier: verifier.into(),
+        }
+    }
+
+    /// The code verifier, sent when creating the access token. Keep it
+    /// secret until then.
+    pub fn verifier(&self) -> &str {
+        &self.verifier
+    }
+
+    /// The code challenge, sent with the authorisation url.
+    pub fn challenge(&self) -> String {
+        let digest = Sha256::digest(self.verifier.as_bytes());
+        base64::encode_config(&digest, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// The method used to derive the challenge, always `S256`.
+    pub fn method(&self) -> &'static str {
+        "S256"
+    }
+
+    /// Whether `challenge` was derived from this verifier.
+    pub fn verify(&self, challenge: &str) -> bool {
+        self.challenge() == challenge
+    }
+}
+
+impl Default for Pkce {
+    fn default() -> Self {
+        Pkce::new()
+    }
+}
+
+impl fmt::Debug for Pkce {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pkce")
+            .field("verifier", &redact::Secret(&self.verifier))
+            .finish()
+    }
+}
+
+/// 32 random bytes, url safe base64 encoded. Long enough to be unguessable,
+/// and within the 43 to 128 characters RFC 7636 allows for a verifier.
+pub(crate) fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill(&mut bytes);
+    base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)
+}
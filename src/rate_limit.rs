@@ -0,0 +1,78 @@
+//! Tracking the request budget an instance reports in the `X-RateLimit-*`
+//! headers.
+
+use std::time::Duration;
+
+use chrono::prelude::*;
+use reqwest::header::HeaderMap;
+
+/// The request budget reported by the instance with its latest response.
+///
+/// ```
+/// # extern crate mammut;
+/// # fn main() -> mammut::Result<()> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let mastodon = server.mastodon();
+/// mastodon.verify_credentials()?;
+///
+/// let budget = mastodon.rate_limit().unwrap();
+/// println!("{} of {} requests left", budget.remaining, budget.limit);
+/// # assert_eq!(budget.remaining, budget.limit - 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    /// The number of requests allowed in each period.
+    pub limit: u64,
+    /// The number of requests left in the current period.
+    pub remaining: u64,
+    /// When the current period ends and `remaining` is reset to `limit`.
+    pub reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+    /// Parse the budget from the `X-RateLimit-Limit`, `X-RateLimit-Remaining`
+    /// and `X-RateLimit-Reset` headers, returning `None` if any of them are
+    /// missing or malformed.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+        let limit = header("x-ratelimit-limit")?.trim().parse().ok()?;
+        let remaining = header("x-ratelimit-remaining")?.trim().parse().ok()?;
+        let reset = header("x-ratelimit-reset")?.trim();
+
+        // Mastodon sends an ISO 8601 timestamp, other implementations send
+        // seconds since the epoch.
+        let reset = match DateTime::parse_from_rfc3339(reset) {
+            Ok(reset) => reset.with_timezone(&Utc),
+            Err(_) => Utc.timestamp_opt(reset.parse().ok()?, 0).single()?,
+        };
+
+        Some(RateLimit {
+            limit,
+            remaining,
+            reset,
+        })
+    }
+
+    /// Whether there are no requests left in the current period.
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// How long until the current period ends, or `None` if it already has.
+    pub fn time_until_reset(&self) -> Option<Duration> {
+        (self.reset - Utc::now()).to_std().ok()
+    }
+
+    /// How long to wait before sending another request, or `None` if the
+    /// budget isn't exhausted.
+    pub(crate) fn wait_time(&self) -> Option<Duration> {
+        if self.is_exhausted() {
+            self.time_until_reset()
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Keeping access tokens and other secrets out of `Debug` output and logs.
+
+use std::fmt;
+
+use reqwest::header::{self, HeaderMap, HeaderName};
+use url::form_urlencoded::byte_serialize;
+use url::Url;
+
+/// What secrets are replaced with.
+pub(crate) const REDACTED: &str = "[redacted]";
+
+// Query parameters whose values are secret.
+const SECRET_PARAMS: &[&str] = &[
+    "access_token",
+    "client_secret",
+    "code",
+    "code_verifier",
+    "password",
+    "refresh_token",
+    "token",
+];
+
+fn is_secret_header(name: &HeaderName) -> bool {
+    name == header::AUTHORIZATION
+        || name == header::PROXY_AUTHORIZATION
+        || name == header::COOKIE
+        || name == header::SET_COOKIE
+}
+
+/// Formats headers with the values of `Authorization`, cookies and the like
+/// redacted.
+pub(crate) struct Headers<'a>(pub &'a HeaderMap);
+
+impl fmt::Debug for Headers<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut map = f.debug_map();
+
+        for (name, value) in self.0 {
+            if is_secret_header(name) {
+                map.entry(name, &format_args!("{}", REDACTED));
+            } else {
+                map.entry(name, value);
+            }
+        }
+
+        map.finish()
+    }
+}
+
+/// Formats a url with the values of secret query parameters, such as
+/// `access_token`, redacted.
+pub(crate) struct Link<'a>(pub &'a Url);
+
+impl Link<'_> {
+    fn has_secrets(&self) -> bool {
+        self.0
+            .query_pairs()
+            .any(|(key, _)| SECRET_PARAMS.contains(&&*key))
+    }
+}
+
+impl fmt::Display for Link<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.has_secrets() {
+            return f.write_str(self.0.as_str());
+        }
+
+        let query = self
+            .0
+            .query_pairs()
+            .map(|(key, value)| {
+                let value = if SECRET_PARAMS.contains(&&*key) {
+                    REDACTED.to_owned()
+                } else {
+                    byte_serialize(value.as_bytes()).collect()
+                };
+                format!(
+                    "{}={}",
+                    byte_serialize(key.as_bytes()).collect::<String>(),
+                    value
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut url = self.0.clone();
+        url.set_query(Some(&query));
+        f.write_str(url.as_str())
+    }
+}
+
+impl fmt::Debug for Link<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
+
+/// Formats a secret as `[redacted]`, or `""` if it's empty so it's still
+/// clear whether it was set.
+pub(crate) struct Secret<'a>(pub &'a str);
+
+impl fmt::Debug for Secret<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            f.write_str("\"\"")
+        } else {
+            f.write_str(REDACTED)
+        }
+    }
+}
+
+/// Formats an error from `reqwest`, whose url may contain secrets.
+pub(crate) struct HttpError<'a>(pub &'a reqwest::Error);
+
+impl HttpError<'_> {
+    // The url if it needs redacting.
+    fn secret_url(&self) -> Option<&Url> {
+        self.0.url().filter(|url| Link(url).has_secrets())
+    }
+}
+
+impl fmt::Display for HttpError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.secret_url(), self.0.get_ref()) {
+            (Some(url), Some(source)) => write!(f, "{}: {}", Link(url), source),
+            (Some(url), None) => write!(f, "{}: request failed", Link(url)),
+            (None, _) => self.0.fmt(f),
+        }
+    }
+}
+
+impl fmt::Debug for HttpError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.secret_url() {
+            Some(url) => f
+                .debug_struct("Error")
+                .field("url", &Link(url))
+                .field("source", &self.0.get_ref())
+                .finish(),
+            None => self.0.fmt(f),
+        }
+    }
+}
@@ -1,29 +1,272 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use reqwest::Client;
+use url::Url;
 
-use super::{Error, Mastodon, Result};
-use crate::apps::{AppBuilder, Scopes};
+use super::{check_status, log_response, redact, Data, Error, Mastodon, Result};
+use crate::apps::{AppBuilder, Scope, Scopes};
+use crate::loopback::Loopback;
+use crate::pkce::{self, Pkce};
+use crate::transport::{Request, Response, Transport};
 
 /// Handles registering your mastodon app to your instance. It is recommended
 /// you cache your data struct to avoid registering on every run.
+///
+/// An app only needs registering once, however many users authorise it.
+/// Save the `RegisteredApp` from `Registration::app` and create each user's
+/// registration with `Registration::from_app`. If the user finishes
+/// authorising in another request or process, as in a web app, save the
+/// `Authorisation` from `Registration::authorisation` along with the
+/// session and `resume` it when the redirect comes back.
+///
+/// ```
+/// # extern crate mammut;
+/// # extern crate serde_json;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let server = mammut::testing::MockServer::start();
+/// use mammut::Registration;
+/// use mammut::apps::AppBuilder;
+/// use mammut::registration::{Authorisation, RegisteredApp};
+///
+/// let app = AppBuilder {
+///     client_name: "mammut_test",
+///     redirect_uris: "https://example.com/callback",
+///     ..AppBuilder::default()
+/// };
+///
+/// // Once, when setting up the web app.
+/// # let mut registration = server.registration();
+/// # /*
+/// let mut registration = Registration::new("https://mastodon.social");
+/// # */
+/// registration.register(app)?;
+/// let stored_app = serde_json::to_string(&registration.app()?)?;
+///
+/// // When a user logs in.
+/// let app: RegisteredApp = serde_json::from_str(&stored_app)?;
+/// let mut registration = Registration::from_app(app);
+/// let url = registration.authorise()?;
+/// let session = serde_json::to_string(&registration.authorisation())?;
+/// // Redirect the user to `url`.
+/// # let url = url::Url::parse(&url).unwrap();
+/// # let state = url.query_pairs().find(|(k, _)| k == "state").unwrap().1;
+/// # let redirect = format!("https://example.com/callback?code=abc&state={}", state);
+///
+/// // When the instance redirects the user back to the web app.
+/// let app: RegisteredApp = serde_json::from_str(&stored_app)?;
+/// let authorisation: Option<Authorisation> = serde_json::from_str(&session)?;
+/// let mut registration = Registration::from_app(app);
+/// registration.resume(authorisation.unwrap());
+/// let mastodon = registration.complete_authorisation(&redirect)?;
+/// # assert_eq!(server.requests().len(), 2);
+/// # use mammut::apps::{Scope, Scopes};
+/// # let mut other = Registration::from_app(serde_json::from_str(&stored_app)?);
+/// # other.authorise_with_scopes(Scope::read("statuses").into())?;
+/// # let mut resumed = Registration::from_app(serde_json::from_str(&stored_app)?);
+/// # resumed.resume(other.authorisation().unwrap());
+/// # assert_eq!(resumed.app()?.scopes, Scopes::Read);
+/// # assert_eq!(resumed.authorisation().unwrap().scopes, Scope::read("statuses").into());
+/// # Ok(())
+/// # }
+/// ```
 pub struct Registration {
+    transport: Arc<dyn Transport>,
+    inner: RegistrationState,
+}
+
+/// Everything a registration keeps track of, shared by `Registration` and
+/// `r#async::Registration`, which only differ in how they send requests.
+pub(crate) struct RegistrationState {
     base: String,
-    client: Client,
     client_id: Option<String>,
     client_secret: Option<String>,
     redirect: Option<String>,
     scopes: Scopes,
+    // The scopes of an app being registered, which become `scopes` once the
+    // instance has registered it.
+    registering_scopes: Option<Scopes>,
+    requested_scopes: Option<Scopes>,
+    state: Option<String>,
+    pkce: Option<Pkce>,
+    metadata: Option<ServerMetadata>,
 }
 
 #[derive(Deserialize)]
-struct OAuth {
+pub(crate) struct OAuth {
     client_id: String,
     client_secret: String,
     redirect_uri: String,
 }
 
 #[derive(Deserialize)]
-struct AccessToken {
-    access_token: String,
+pub(crate) struct AccessToken {
+    pub(crate) access_token: String,
+    // Missing from older instances, which grant the scopes asked for.
+    #[serde(default)]
+    pub(crate) scope: Option<Scopes>,
+}
+
+/// An app registered with an instance, which can be saved and used to
+/// authorise any number of users with `Registration::from_app`.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisteredApp {
+    /// Base url of the instance, e.g. `https://mastodon.social`.
+    pub base: String,
+    /// The app's client id.
+    pub client_id: String,
+    /// The app's client secret.
+    pub client_secret: String,
+    /// The redirect uri the app was registered with.
+    pub redirect: String,
+    /// The scopes the app was registered with.
+    #[serde(default)]
+    pub scopes: Scopes,
+}
+
+impl fmt::Debug for RegisteredApp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RegisteredApp")
+            .field("base", &self.base)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &redact::Secret(&self.client_secret))
+            .field("redirect", &self.redirect)
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+/// The state of one user's authorisation in progress, from `authorise` until
+/// the redirect back from the instance. It has to be kept secret from
+/// everyone but the user.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Authorisation {
+    /// The `state` sent with the authorisation url.
+    pub state: String,
+    /// The PKCE code verifier whose challenge was sent with the
+    /// authorisation url.
+    pub pkce: Pkce,
+    /// The scopes asked for.
+    pub scopes: Scopes,
+}
+
+/// What the instance says about its OAuth server at
+/// `/.well-known/oauth-authorization-server`, as described in
+/// [RFC 8414](https://tools.ietf.org/html/rfc8414). Published by Mastodon 4.3
+/// and later.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ServerMetadata {
+    /// The url of the OAuth server.
+    pub issuer: String,
+    /// The url of the OAuth server's documentation.
+    pub service_documentation: Option<String>,
+    /// The url to send users to for authorisation.
+    pub authorization_endpoint: String,
+    /// The url for creating access tokens.
+    pub token_endpoint: String,
+    /// The url for registering apps.
+    pub app_registration_endpoint: Option<String>,
+    /// The url for revoking access tokens.
+    pub revocation_endpoint: Option<String>,
+    /// Every scope apps can ask for. Empty if the instance doesn't say, in
+    /// which case scopes aren't checked against it.
+    #[serde(default = "Scopes::empty", with = "crate::apps::scope_list")]
+    pub scopes_supported: Scopes,
+    /// The supported `response_type`s of the authorisation url.
+    #[serde(default)]
+    pub response_types_supported: Vec<String>,
+    /// The supported `grant_type`s of the token endpoint.
+    #[serde(default)]
+    pub grant_types_supported: Vec<String>,
+    /// The supported PKCE code challenge methods.
+    #[serde(default)]
+    pub code_challenge_methods_supported: Vec<String>,
+    /// The ways apps can authenticate to the token endpoint.
+    #[serde(default)]
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+}
+
+impl ServerMetadata {
+    /// Whether `scope` is one of the scopes the instance supports.
+    pub fn supports_scope(&self, scope: &Scope) -> bool {
+        self.scopes_supported
+            .iter()
+            .any(|supported| supported == scope)
+    }
+
+    /// Fail with `Error::UnsupportedScope` for the first of `scopes` the
+    /// instance doesn't support, if it lists the scopes it supports.
+    pub(crate) fn check_scopes(&self, scopes: &Scopes) -> Result<()> {
+        if self.scopes_supported.is_empty() {
+            return Ok(());
+        }
+
+        match scopes.iter().find(|scope| !self.supports_scope(scope)) {
+            Some(scope) => Err(Error::UnsupportedScope(scope.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Fail with `Error::UnregisteredScope` for the first of `requested` that
+/// isn't among the `registered` scopes of the app.
+pub(crate) fn check_registered_scopes(registered: &Scopes, requested: &Scopes) -> Result<()> {
+    match requested.iter().find(|scope| !registered.contains(scope)) {
+        Some(scope) => Err(Error::UnregisteredScope(scope.clone())),
+        None => Ok(()),
+    }
+}
+
+/// The OAuth urls of an instance, from its `ServerMetadata` if discovered.
+pub(crate) struct Endpoints {
+    pub(crate) apps: String,
+    pub(crate) authorize: String,
+    pub(crate) token: String,
+    pub(crate) revoke: String,
+}
+
+impl Endpoints {
+    pub(crate) fn new(base: &str, metadata: Option<&ServerMetadata>) -> Self {
+        let advertised = |endpoint: Option<&String>, path: &str| {
+            endpoint
+                .cloned()
+                .unwrap_or_else(|| format!("{}{}", base, path))
+        };
+
+        Endpoints {
+            apps: advertised(
+                metadata.and_then(|m| m.app_registration_endpoint.as_ref()),
+                "/api/v1/apps",
+            ),
+            authorize: advertised(
+                metadata.map(|m| &m.authorization_endpoint),
+                "/oauth/authorize",
+            ),
+            token: advertised(metadata.map(|m| &m.token_endpoint), "/oauth/token"),
+            revoke: advertised(
+                metadata.and_then(|m| m.revocation_endpoint.as_ref()),
+                "/oauth/revoke",
+            ),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TokenForm<'a> {
+    grant_type: &'a str,
+    code: &'a str,
+    client_id: &'a str,
+    client_secret: &'a str,
+    redirect_uri: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code_verifier: Option<&'a str>,
+}
+
+impl fmt::Debug for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt_with_transport(f, &self.transport)
+    }
 }
 
 impl Registration {
@@ -34,21 +277,131 @@ impl Registration {
     /// let registration = Registration::new("https://mastodon.social");
     /// ```
     pub fn new<I: Into<String>>(base: I) -> Self {
+        Self::new_with_transport(base, Client::new())
+    }
+
+    /// Construct a new registration process to the instance of the `base` url,
+    /// sending all of its requests through `transport`. The `Mastodon` client
+    /// created by the registration shares the same transport.
+    pub fn new_with_transport<I, T>(base: I, transport: T) -> Self
+    where
+        I: Into<String>,
+        T: Transport + 'static,
+    {
         Registration {
-            base: base.into(),
-            client: Client::new(),
-            client_id: None,
-            client_secret: None,
-            redirect: None,
-            scopes: Scopes::Read,
+            transport: Arc::new(transport),
+            inner: RegistrationState::new(base.into()),
         }
     }
 
+    /// Continue the registration of an app that is already registered.
+    pub fn from_app(app: RegisteredApp) -> Self {
+        Self::from_app_with_transport(app, Client::new())
+    }
+
+    /// Continue the registration of an app that is already registered,
+    /// sending all of its requests through `transport`.
+    pub fn from_app_with_transport<T: Transport + 'static>(
+        app: RegisteredApp,
+        transport: T,
+    ) -> Self {
+        Registration {
+            transport: Arc::new(transport),
+            inner: RegistrationState::from_app(app),
+        }
+    }
+
+    /// The registered app, to save for `from_app`.
+    ///
+    /// # Errors
+    /// If the app hasn't been registered.
+    pub fn app(&self) -> Result<RegisteredApp> {
+        self.inner.app()
+    }
+
+    /// The authorisation in progress since the latest `authorise`, to save
+    /// until the redirect comes back.
+    pub fn authorisation(&self) -> Option<Authorisation> {
+        self.inner.authorisation()
+    }
+
+    /// Continue an authorisation saved from `authorisation`, so the redirect
+    /// can be completed by this registration. The scopes the app was
+    /// registered with are kept.
+    pub fn resume(&mut self, authorisation: Authorisation) {
+        self.inner.resume(authorisation)
+    }
+
+    /// Fetch the instance's `ServerMetadata`. From then on, scopes are
+    /// checked against the supported ones before registering or
+    /// authorising, failing with `Error::UnsupportedScope`, and the
+    /// registration uses the advertised urls instead of the default
+    /// `/api/v1/apps`, `/oauth/authorize`, `/oauth/token` and
+    /// `/oauth/revoke`.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # extern crate reqwest;
+    /// # extern crate serde_json;
+    /// # fn main() -> mammut::Result<()> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// use mammut::apps::{AppBuilder, Scope, Scopes};
+    /// use mammut::Error;
+    ///
+    /// # let mut registration = server.registration();
+    /// # /*
+    /// let mut registration = Registration::new("https://mastodon.social");
+    /// # */
+    /// let metadata = registration.discover()?;
+    /// assert!(metadata.supports_scope(&Scope::write("media")));
+    /// # let json = serde_json::to_string(metadata)?;
+    /// # assert_eq!(&serde_json::from_str::<mammut::registration::ServerMetadata>(&json)?, metadata);
+    ///
+    /// let app = AppBuilder {
+    ///     client_name: "mammut_test",
+    ///     scopes: Scopes::Read | Scope::new("write:everything"),
+    ///     ..AppBuilder::default()
+    /// };
+    /// match registration.register(app) {
+    ///     Err(Error::UnsupportedScope(scope)) => assert_eq!(scope.as_str(), "write:everything"),
+    ///     other => panic!("{:?}", other),
+    /// }
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let mut metadata = serde_json::to_value(server.registration().discover()?)?;
+    /// # metadata.as_object_mut().unwrap().remove("scopes_supported");
+    /// # server.mock(reqwest::Method::GET, "/.well-known/oauth-authorization-server",
+    /// #     mammut::testing::MockResponse::json(reqwest::StatusCode::OK, &metadata));
+    /// # let mut registration = server.registration();
+    /// # assert!(registration.discover()?.scopes_supported.is_empty());
+    /// # registration.register(AppBuilder {
+    /// #     client_name: "mammut_test",
+    /// #     scopes: Scope::new("write:everything").into(),
+    /// #     ..AppBuilder::default()
+    /// # })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    /// If the instance doesn't publish its metadata, as before Mastodon 4.3.
+    pub fn discover(&mut self) -> Result<&ServerMetadata> {
+        let metadata = self.send(self.inner.discover_request()?)?.json()?;
+
+        Ok(self.inner.discovered(metadata))
+    }
+
+    /// The instance's `ServerMetadata`, if it has been discovered.
+    pub fn metadata(&self) -> Option<&ServerMetadata> {
+        self.inner.metadata()
+    }
+
     /// Register the application with the server from the `base` url.
     ///
-    /// ```no_run
+    /// ```
     /// # extern crate mammut;
+    /// # extern crate reqwest;
     /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let server = mammut::testing::MockServer::start();
     /// use mammut::Registration;
     /// use mammut::apps::{AppBuilder, Scopes};
     ///
@@ -59,7 +412,11 @@ impl Registration {
     ///     website: None,
     /// };
     ///
-    /// let mut registration = Registration::new("https://mastodon.social");
+    /// # let instance = server.base();
+    /// # /*
+    /// let instance = "https://mastodon.social";
+    /// # */
+    /// let mut registration = Registration::new(instance);
     /// registration.register(app)?;
     /// let url = registration.authorise()?;
     /// // Here you now need to open the url in the browser
@@ -68,37 +425,476 @@ impl Registration {
     /// let mastodon = registration.create_access_token(code)?;
     ///
     /// println!("{:?}", mastodon.get_home_timeline()?.initial_items);
+    /// # use mammut::testing::MockResponse;
+    /// # let failed = MockResponse::new(reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    /// # server.mock_once(reqwest::Method::POST, "/api/v1/apps", failed);
+    /// # let all = AppBuilder { scopes: Scopes::All, ..AppBuilder::default() };
+    /// # assert!(registration.register(all).is_err());
+    /// # assert_eq!(registration.app()?.scopes, Scopes::Read);
     /// # Ok(())
     /// # }
     /// ```
     pub fn register(&mut self, app_builder: AppBuilder) -> Result<()> {
-        let url = format!("{}/api/v1/apps", self.base);
-        self.scopes = app_builder.scopes;
-        let app: OAuth = self.client.post(&url).form(&app_builder).send()?.json()?;
-
-        self.client_id = Some(app.client_id);
-        self.client_secret = Some(app.client_secret);
-        self.redirect = Some(app.redirect_uri);
+        let request = self.inner.register_request(&app_builder)?;
+        let app = self.send(request)?.json()?;
+        self.inner.registered(app);
 
         Ok(())
     }
 
+    /// Register the application like `register`, with a `Loopback` listener
+    /// on `127.0.0.1:port` as its redirect uri, replacing the builder's
+    /// `redirect_uris`. Pass `0` to have the OS pick a free port.
+    ///
+    /// Open the url from `authorise` in the browser, then finish with
+    /// `complete_loopback`, which waits for the redirect.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # extern crate reqwest;
+    /// # fn main() -> mammut::Result<()> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// use std::time::Duration;
+    /// use mammut::apps::{AppBuilder, Scopes};
+    ///
+    /// let app = AppBuilder {
+    ///     client_name: "mammut_test",
+    ///     scopes: Scopes::Read,
+    ///     ..AppBuilder::default()
+    /// };
+    ///
+    /// # let mut registration = server.registration();
+    /// # /*
+    /// let mut registration = Registration::new("https://mastodon.social");
+    /// # */
+    /// let loopback = registration.register_loopback(app, 0)?;
+    /// let url = registration.authorise()?;
+    /// // Open `url` in the browser. Once the user authorises the app, the
+    /// // instance redirects the browser to the listener.
+    /// # let url = url::Url::parse(&url).unwrap();
+    /// # let state = url.query_pairs().find(|(k, _)| k == "state").unwrap().1.into_owned();
+    /// # let redirect = format!("{}?code=abc&state={}", loopback.redirect_uri(), state);
+    /// # let browser = std::thread::spawn(move || reqwest::get(&redirect).unwrap().status());
+    /// let mastodon = registration.complete_loopback(&loopback, Duration::from_secs(300))?;
+    /// # assert!(browser.join().unwrap().is_success());
+    /// # assert_eq!(server.requests()[1].form_param("code").unwrap(), "abc");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_loopback(&mut self, app_builder: AppBuilder, port: u16) -> Result<Loopback> {
+        let loopback = Loopback::bind(port)?;
+        self.register(AppBuilder {
+            redirect_uris: loopback.redirect_uri(),
+            ..app_builder
+        })?;
+
+        Ok(loopback)
+    }
+
     /// Returns the full url needed for authorisation. This needs to be opened
     /// in a browser.
+    ///
+    /// Each call generates a new random `state` and PKCE code verifier, which
+    /// are checked and sent by `complete_authorisation` and
+    /// `create_access_token`, so only the url from the latest call can be
+    /// used.
     pub fn authorise(&mut self) -> Result<String> {
+        self.inner.authorise()
+    }
+
+    /// Returns the url needed for authorisation like `authorise`, asking the
+    /// user for only `scopes`, which must be among the scopes the app was
+    /// registered with.
+    ///
+    /// # Errors
+    /// `Error::UnregisteredScope` for the first of `scopes` the app wasn't
+    /// registered with.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # fn main() -> mammut::Result<()> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// use mammut::apps::{AppBuilder, Scope, Scopes};
+    /// use mammut::Error;
+    ///
+    /// let app = AppBuilder {
+    ///     client_name: "mammut_test",
+    ///     redirect_uris: "https://example.com/callback",
+    ///     scopes: Scopes::ReadWrite,
+    ///     website: None,
+    /// };
+    ///
+    /// # let mut registration = server.registration();
+    /// # /*
+    /// let mut registration = Registration::new("https://mastodon.social");
+    /// # */
+    /// registration.register(app)?;
+    /// let url = registration.authorise_with_scopes(Scopes::Read)?;
+    /// # assert!(url.contains("scope=read&"));
+    ///
+    /// match registration.authorise_with_scopes(Scope::new("follow").into()) {
+    ///     Err(Error::UnregisteredScope(scope)) => assert_eq!(scope, Scope::new("follow")),
+    ///     _ => unreachable!(),
+    /// }
+    ///
+    /// // The app is still registered with, and authorises, all of its scopes.
+    /// assert_eq!(registration.app()?.scopes, Scopes::ReadWrite);
+    /// # assert!(registration.authorise()?.contains("scope=read+write&"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn authorise_with_scopes(&mut self, scopes: Scopes) -> Result<String> {
+        self.inner.authorise_for(scopes)
+    }
+
+    /// The authorisation code from the url the browser was redirected to
+    /// after the user authorised the app, checking that the redirect carries
+    /// the `state` sent by `authorise`.
+    ///
+    /// # Errors
+    /// `Error::StateMismatch` if the state is missing or different,
+    /// `Error::AuthorisationDenied` if the user or instance refused, and
+    /// `Error::CodeRequired` if there's no code.
+    pub fn code_from_redirect(&self, redirect: &str) -> Result<String> {
+        self.inner.code_from_redirect(redirect)
+    }
+
+    /// Finish authorising from the url the browser was redirected to, see
+    /// `code_from_redirect`, and create an access token with its code.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # fn main() -> mammut::Result<()> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// use mammut::apps::{AppBuilder, Scopes};
+    ///
+    /// let app = AppBuilder {
+    ///     client_name: "mammut_test",
+    ///     redirect_uris: "https://example.com/callback",
+    ///     scopes: Scopes::Read,
+    ///     website: None,
+    /// };
+    ///
+    /// # let mut registration = server.registration();
+    /// # /*
+    /// let mut registration = Registration::new("https://mastodon.social");
+    /// # */
+    /// registration.register(app)?;
+    /// let url = registration.authorise()?;
+    /// # let url = url::Url::parse(&url).unwrap();
+    /// # let state = url.query_pairs().find(|(k, _)| k == "state").unwrap().1;
+    /// # let redirect = format!("https://example.com/callback?code=abc&state={}", state);
+    /// // Send the user to `url`, and once the instance redirects them back
+    /// // to the app:
+    /// let mastodon = registration.complete_authorisation(&redirect)?;
+    /// # let token = &server.requests()[1];
+    /// # assert_eq!(token.form_param("code").unwrap(), "abc");
+    /// # assert!(token.form_param("code_verifier").is_some());
+    /// # assert!(token.query.is_none());
+    ///
+    /// # let mut registration = server.registration();
+    /// # registration.register(AppBuilder::default())?;
+    /// # registration.authorise()?;
+    /// # let forged = "https://example.com/callback?code=abc&state=forged";
+    /// # match registration.complete_authorisation(forged) {
+    /// #     Err(mammut::Error::StateMismatch) => {}
+    /// #     other => panic!("{:?}", other),
+    /// # }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn complete_authorisation(&self, redirect: &str) -> Result<Mastodon> {
+        let code = self.code_from_redirect(redirect)?;
+        self.create_access_token(code)
+    }
+
+    /// Wait up to `timeout` for the browser to be redirected to `loopback`
+    /// after the user authorised the app, then finish authorising like
+    /// `complete_authorisation`. The redirect is checked before the browser
+    /// is answered, so the user is shown an error page if its `state` doesn't
+    /// match or the instance sent an error instead of a code.
+    ///
+    /// # Errors
+    /// `Error::Io` with `io::ErrorKind::TimedOut` if there's no redirect in
+    /// time, and any error from `complete_authorisation`.
+    pub fn complete_loopback(&self, loopback: &Loopback, timeout: Duration) -> Result<Mastodon> {
+        let code = loopback.wait_with(timeout, |redirect| self.code_from_redirect(redirect))?;
+        self.create_access_token(code)
+    }
+
+    /// Revoke an access token of the app, logging its user out. This uses
+    /// the advertised revocation url if the `ServerMetadata` has been
+    /// discovered, as does `Mastodon::revoke` on the clients it creates.
+    pub fn revoke(&self, token: &str) -> Result<()> {
+        self.send(self.inner.revoke_request(token)?)?;
+        Ok(())
+    }
+
+    fn send(&self, request: Request) -> Result<Response> {
+        let start = Instant::now();
+        let response = self.transport.send(request.clone())?;
+        log_response(&request.method, &request.url, &response, start.elapsed());
+
+        check_status(&request.method, &request.url, response)
+    }
+
+    /// Create an access token from the client id, client secret, and code
+    /// provided by the authorisation url. The PKCE code verifier from the
+    /// latest `authorise` is sent along with the code.
+    pub fn create_access_token(&self, code: String) -> Result<Mastodon> {
+        let (request, grant) = self.inner.token_request(&code)?;
+        let token = self.send(request)?.json()?;
+
+        self.mastodon(grant, token)
+    }
+
+    /// Create an access token for the app itself rather than a user, with
+    /// the client credentials grant. It has the scopes the app was
+    /// registered with, and can use routes that don't act for a user, such
+    /// as the public timelines or `Mastodon::register_account`.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # fn main() -> mammut::Result<()> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// use mammut::apps::{AppBuilder, Scope, Scopes};
+    /// use mammut::SignupBuilder;
+    ///
+    /// let app = AppBuilder {
+    ///     client_name: "mammut_test",
+    ///     redirect_uris: "urn:ietf:wg:oauth:2.0:oob",
+    ///     scopes: Scopes::Read | Scope::write("accounts"),
+    ///     website: None,
+    /// };
+    ///
+    /// # let mut registration = server.registration();
+    /// # /*
+    /// let mut registration = Registration::new("https://mastodon.social");
+    /// # */
+    /// registration.register(app)?;
+    /// let mastodon = registration.create_app_token()?;
+    /// # let request = &server.requests()[1];
+    /// # assert_eq!(request.form_param("grant_type").unwrap(), "client_credentials");
+    /// # assert_eq!(request.form_param("scope").unwrap(), "read write:accounts");
+    ///
+    /// let signup = SignupBuilder::new("alice".into(), "alice@example.com".into(), "hunter22".into())
+    ///     .agreement(true);
+    /// let alice = mastodon.register_account(signup)?;
+    /// # assert!(alice.is_authenticated());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_app_token(&self) -> Result<Mastodon> {
+        let (request, grant) = self.inner.app_token_request()?;
+        let token = self.send(request)?.json()?;
+
+        self.mastodon(grant, token)
+    }
+
+    fn mastodon(&self, grant: Grant, token: AccessToken) -> Result<Mastodon> {
+        Mastodon::from_data_with_shared_transport(grant.complete(token), self.transport.clone())
+    }
+}
+
+impl RegistrationState {
+    pub(crate) fn new(base: String) -> Self {
+        RegistrationState {
+            base,
+            client_id: None,
+            client_secret: None,
+            redirect: None,
+            scopes: Scopes::Read,
+            registering_scopes: None,
+            requested_scopes: None,
+            state: None,
+            pkce: None,
+            metadata: None,
+        }
+    }
+
+    pub(crate) fn from_app(app: RegisteredApp) -> Self {
+        RegistrationState {
+            client_id: Some(app.client_id),
+            client_secret: Some(app.client_secret),
+            redirect: Some(app.redirect),
+            scopes: app.scopes,
+            ..Self::new(app.base)
+        }
+    }
+
+    /// The `Debug` output of a registration sending its requests through
+    /// `transport`.
+    pub(crate) fn fmt_with_transport(
+        &self,
+        f: &mut fmt::Formatter,
+        transport: &dyn fmt::Debug,
+    ) -> fmt::Result {
+        f.debug_struct("Registration")
+            .field("base", &self.base)
+            .field("transport", transport)
+            .field("client_id", &self.client_id)
+            .field(
+                "client_secret",
+                &self.client_secret.as_ref().map(|s| redact::Secret(s)),
+            )
+            .field("redirect", &self.redirect)
+            .field("scopes", &self.scopes)
+            .field("state", &self.state)
+            .field("pkce", &self.pkce)
+            .field("metadata", &self.metadata)
+            .finish()
+    }
+
+    pub(crate) fn app(&self) -> Result<RegisteredApp> {
         self.is_registered()?;
 
-        let url = format!(
-            "{}/oauth/authorize?client_id={}&redirect_uri={}&scope={}&response_type=code",
-            self.base,
-            self.client_id.clone().unwrap(),
-            self.redirect.clone().unwrap(),
-            self.scopes,
-        );
+        Ok(RegisteredApp {
+            base: self.base.clone(),
+            client_id: self.client_id.clone().unwrap(),
+            client_secret: self.client_secret.clone().unwrap(),
+            redirect: self.redirect.clone().unwrap(),
+            scopes: self.scopes.clone(),
+        })
+    }
+
+    pub(crate) fn authorisation(&self) -> Option<Authorisation> {
+        match (&self.state, &self.pkce) {
+            (Some(state), Some(pkce)) => Some(Authorisation {
+                state: state.clone(),
+                pkce: pkce.clone(),
+                scopes: self
+                    .requested_scopes
+                    .clone()
+                    .unwrap_or_else(|| self.scopes.clone()),
+            }),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn resume(&mut self, authorisation: Authorisation) {
+        self.state = Some(authorisation.state);
+        self.pkce = Some(authorisation.pkce);
+        self.requested_scopes = Some(authorisation.scopes);
+    }
+
+    pub(crate) fn discover_request(&self) -> Result<Request> {
+        Request::get(&format!(
+            "{}/.well-known/oauth-authorization-server",
+            self.base
+        ))
+    }
+
+    pub(crate) fn discovered(&mut self, metadata: ServerMetadata) -> &ServerMetadata {
+        self.metadata.insert(metadata)
+    }
+
+    pub(crate) fn metadata(&self) -> Option<&ServerMetadata> {
+        self.metadata.as_ref()
+    }
+
+    pub(crate) fn register_request(&mut self, app_builder: &AppBuilder) -> Result<Request> {
+        if let Some(ref metadata) = self.metadata {
+            metadata.check_scopes(&app_builder.scopes)?;
+        }
+
+        let request = Request::post(&self.endpoints().apps)?.form(app_builder)?;
+        self.registering_scopes = Some(app_builder.scopes.clone());
+        Ok(request)
+    }
+
+    pub(crate) fn registered(&mut self, app: OAuth) {
+        if let Some(scopes) = self.registering_scopes.take() {
+            self.scopes = scopes;
+        }
+        self.client_id = Some(app.client_id);
+        self.client_secret = Some(app.client_secret);
+        self.redirect = Some(app.redirect_uri);
+    }
+
+    pub(crate) fn authorise(&mut self) -> Result<String> {
+        let scopes = self.scopes.clone();
+        self.authorise_for(scopes)
+    }
+
+    pub(crate) fn authorise_for(&mut self, scopes: Scopes) -> Result<String> {
+        self.is_registered()?;
+        check_registered_scopes(&self.scopes, &scopes)?;
+        if let Some(ref metadata) = self.metadata {
+            metadata.check_scopes(&scopes)?;
+        }
+
+        let state = pkce::random_token();
+        let pkce = Pkce::new();
+        let url = authorise_url(
+            &self.endpoints().authorize,
+            self.client_id.as_ref().unwrap(),
+            self.redirect.as_ref().unwrap(),
+            &scopes,
+            &state,
+            &pkce,
+        )?;
+
+        self.state = Some(state);
+        self.pkce = Some(pkce);
+        self.requested_scopes = Some(scopes);
 
         Ok(url)
     }
 
+    pub(crate) fn code_from_redirect(&self, redirect: &str) -> Result<String> {
+        code_from_redirect(redirect, self.state.as_deref())
+    }
+
+    pub(crate) fn token_request(&self, code: &str) -> Result<(Request, Grant)> {
+        let app = self.app()?;
+        let request = token_request(
+            &self.endpoints().token,
+            &app.client_id,
+            &app.client_secret,
+            &app.redirect,
+            code,
+            self.pkce.as_ref(),
+        )?;
+        let scopes = self
+            .requested_scopes
+            .clone()
+            .unwrap_or_else(|| app.scopes.clone());
+
+        Ok((request, self.grant(app, scopes)))
+    }
+
+    pub(crate) fn app_token_request(&self) -> Result<(Request, Grant)> {
+        let app = self.app()?;
+        let request = app_token_request(&self.endpoints().token, &app)?;
+        let scopes = app.scopes.clone();
+
+        Ok((request, self.grant(app, scopes)))
+    }
+
+    pub(crate) fn revoke_request(&self, token: &str) -> Result<Request> {
+        let app = self.app()?;
+        Request::post(&self.endpoints().revoke)?.form(&[
+            ("client_id", &*app.client_id),
+            ("client_secret", &*app.client_secret),
+            ("token", token),
+        ])
+    }
+
+    fn grant(&self, app: RegisteredApp, scopes: Scopes) -> Grant {
+        Grant {
+            app,
+            scopes,
+            revocation_endpoint: self
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.revocation_endpoint.clone()),
+        }
+    }
+
+    fn endpoints(&self) -> Endpoints {
+        Endpoints::new(&self.base, self.metadata.as_ref())
+    }
+
     fn is_registered(&self) -> Result<()> {
         if self.client_id.is_none() {
             Err(Error::ClientIdRequired)
@@ -108,29 +904,163 @@ impl Registration {
             Ok(())
         }
     }
+}
 
-    /// Create an access token from the client id, client secret, and code
-    /// provided by the authorisation url.
-    pub fn create_access_token(self, code: String) -> Result<Mastodon> {
-        self.is_registered()?;
-        let url = format!(
-            "{}/oauth/token?client_id={}&client_secret={}&code={}&grant_type=authorization_code&redirect_uri={}",
-            self.base,
-            self.client_id.clone().unwrap(),
-            self.client_secret.clone().unwrap(),
-            code,
-            self.redirect.clone().unwrap()
-        );
-
-        let token: AccessToken = self.client.post(&url).send()?.json()?;
-
-        Ok(Mastodon::from_registration(
-            self.base,
-            self.client_id.unwrap(),
-            self.client_secret.unwrap(),
-            self.redirect.unwrap(),
-            token.access_token,
-            self.client,
-        ))
+/// The app an access token is asked for, the scopes it's granted unless the
+/// instance says otherwise, and where to revoke it if the instance said.
+pub(crate) struct Grant {
+    app: RegisteredApp,
+    scopes: Scopes,
+    revocation_endpoint: Option<String>,
+}
+
+impl Grant {
+    /// The `Data` of a client with the access token the instance granted.
+    pub(crate) fn complete(self, token: AccessToken) -> Data {
+        Data {
+            base: self.app.base.into(),
+            client_id: self.app.client_id.into(),
+            client_secret: self.app.client_secret.into(),
+            redirect: self.app.redirect.into(),
+            token: token.access_token.into(),
+            scopes: Some(token.scope.unwrap_or(self.scopes)),
+            revocation_endpoint: self.revocation_endpoint.map(Into::into),
+        }
+    }
+}
+
+/// The authorisation url, with its query properly encoded.
+fn authorise_url(
+    endpoint: &str,
+    client_id: &str,
+    redirect: &str,
+    scopes: &Scopes,
+    state: &str,
+    pkce: &Pkce,
+) -> Result<String> {
+    let url = Url::parse_with_params(
+        endpoint,
+        &[
+            ("client_id", client_id),
+            ("redirect_uri", redirect),
+            ("scope", &scopes.to_string()),
+            ("response_type", "code"),
+            ("state", state),
+            ("code_challenge", &pkce.challenge()),
+            ("code_challenge_method", pkce.method()),
+        ],
+    )?;
+
+    Ok(url.into_string())
+}
+
+/// The request exchanging an authorisation code for an access token, with
+/// the secrets in a form body rather than the url.
+fn token_request(
+    endpoint: &str,
+    client_id: &str,
+    client_secret: &str,
+    redirect: &str,
+    code: &str,
+    pkce: Option<&Pkce>,
+) -> Result<Request> {
+    Request::post(endpoint)?.form(&TokenForm {
+        grant_type: "authorization_code",
+        code,
+        client_id,
+        client_secret,
+        redirect_uri: redirect,
+        code_verifier: pkce.map(Pkce::verifier),
+    })
+}
+
+/// The request for an app token with the client credentials grant.
+fn app_token_request(endpoint: &str, app: &RegisteredApp) -> Result<Request> {
+    Request::post(endpoint)?.form(&[
+        ("grant_type", "client_credentials"),
+        ("client_id", &app.client_id),
+        ("client_secret", &app.client_secret),
+        ("scope", &app.scopes.to_string()),
+    ])
+}
+
+/// The code from the redirect after authorisation, if its `state` is
+/// `expected`.
+fn code_from_redirect(redirect: &str, expected: Option<&str>) -> Result<String> {
+    let url = Url::parse(redirect)?;
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+
+    if param("state").as_deref() != expected {
+        return Err(Error::StateMismatch);
+    }
+
+    if let Some(error) = param("error") {
+        return Err(Error::AuthorisationDenied(
+            param("error_description").unwrap_or(error),
+        ));
+    }
+
+    param("code").ok_or(Error::CodeRequired)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::testing::MockServer;
+
+    // Complete a loopback authorisation with the browser redirected to the
+    // listener with the query `query(state)`, returning the outcome and the
+    // status of the page the browser was shown.
+    fn complete_loopback(query: fn(&str) -> String) -> (Result<Mastodon>, StatusCode) {
+        let server = MockServer::start();
+        let mut registration = server.registration();
+        let loopback = registration
+            .register_loopback(AppBuilder::default(), 0)
+            .unwrap();
+        let url = Url::parse(&registration.authorise().unwrap()).unwrap();
+        let state = url
+            .query_pairs()
+            .find(|(key, _)| key == "state")
+            .unwrap()
+            .1
+            .into_owned();
+
+        let redirect = format!("{}?{}", loopback.redirect_uri(), query(&state));
+        let browser = thread::spawn(move || reqwest::get(&redirect).unwrap().status());
+        let result = registration.complete_loopback(&loopback, Duration::from_secs(5));
+
+        (result, browser.join().unwrap())
+    }
+
+    #[test]
+    fn loopback_rejects_a_forged_state() {
+        let (result, status) = complete_loopback(|_| "code=abc&state=forged".into());
+
+        match result {
+            Err(Error::StateMismatch) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn loopback_returns_a_denied_authorisation() {
+        let (result, status) =
+            complete_loopback(|state| format!("error=access_denied&state={}", state));
+
+        match result {
+            Err(Error::AuthorisationDenied(ref error)) if error == "access_denied" => {}
+            other => panic!("{:?}", other.err()),
+        }
+        assert_eq!(status, StatusCode::BAD_REQUEST);
     }
 }
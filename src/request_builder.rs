@@ -0,0 +1,107 @@
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use super::{Mastodon, Result};
+use crate::page::Page;
+use crate::transport::{Multipart, Request, Response};
+
+/// A builder pattern struct for sending a request to any route, including
+/// ones mammut doesn't have a method for yet. Created by
+/// `Mastodon::request`.
+///
+/// The request is sent with the client's access token and headers, and goes
+/// through the same rate limiting, retries and error handling as every other
+/// route. Errors while building the request are returned once it's sent.
+///
+/// ```
+/// # extern crate mammut;
+/// # extern crate reqwest;
+/// # fn main() -> mammut::Result<()> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let mastodon = server.mastodon();
+/// use mammut::entities::account::Account;
+/// use mammut::entities::status::Status;
+/// use reqwest::Method;
+///
+/// let account: Account = mastodon
+///     .request(Method::GET, "/api/v1/accounts/1")
+///     .send()?;
+///
+/// let statuses = mastodon
+///     .request(Method::GET, "/api/v1/timelines/home")
+///     .query(&[("limit", "1")])
+///     .paged::<Status>()?;
+/// # assert_eq!(statuses.initial_items.len(), 1);
+///
+/// let response = mastodon
+///     .request(Method::POST, "/api/v1/statuses")
+///     .json(&serde_json::json!({ "status": "Hello" }))
+///     .raw()?;
+/// # assert!(response.status.is_success());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RequestBuilder<'a> {
+    mastodon: &'a Mastodon,
+    request: Result<Request>,
+}
+
+impl<'a> RequestBuilder<'a> {
+    pub(crate) fn new(mastodon: &'a Mastodon, method: Method, path: &str) -> Self {
+        RequestBuilder {
+            mastodon,
+            request: Request::new(method, &mastodon.route(path)),
+        }
+    }
+
+    /// Append `query` serialised as a url encoded form to the query string.
+    pub fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.request = self.request.and_then(|request| request.query(query));
+        self
+    }
+
+    /// Add a header to the request, replacing the client's header of the
+    /// same name.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        if let Ok(ref mut request) = self.request {
+            request.headers.insert(name, value);
+        }
+        self
+    }
+
+    /// Set the body to `body` serialised as JSON.
+    pub fn json<T: Serialize + ?Sized>(mut self, body: &T) -> Self {
+        self.request = self.request.and_then(|request| request.json(body));
+        self
+    }
+
+    /// Set the body to `body` serialised as a url encoded form.
+    pub fn form<T: Serialize + ?Sized>(mut self, body: &T) -> Self {
+        self.request = self.request.and_then(|request| request.form(body));
+        self
+    }
+
+    /// Set the body to a multipart form, for uploading files.
+    pub fn multipart(mut self, form: Multipart) -> Self {
+        self.request = self.request.map(|request| request.multipart(form));
+        self
+    }
+
+    /// Send the request and deserialise the response body from JSON.
+    pub fn send<T: for<'de> Deserialize<'de>>(self) -> Result<T> {
+        self.raw()?.json()
+    }
+
+    /// Send the request to a paginated route, returning the first page.
+    pub fn paged<T: for<'de> Deserialize<'de>>(self) -> Result<Page<'a, T>> {
+        let mastodon = self.mastodon;
+        Page::new(mastodon, self.raw()?)
+    }
+
+    /// Send the request, returning the response as is.
+    pub fn raw(self) -> Result<Response> {
+        self.mastodon.send(self.request?)
+    }
+}
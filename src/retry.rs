@@ -0,0 +1,207 @@
+//! Retrying requests that fail with `429 Too Many Requests` or a temporary
+//! server error.
+
+use std::cmp;
+use std::time::{Duration, SystemTime};
+
+use hyperx::header::{Header, RetryAfter};
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Method, StatusCode};
+
+use crate::transport::Response;
+
+/// How the client retries requests that fail with `429 Too Many Requests`,
+/// `500 Internal Server Error`, `502 Bad Gateway`, `503 Service Unavailable`
+/// or `504 Gateway Timeout`.
+///
+/// The delay before each retry is taken from the response's `Retry-After`
+/// header if it has one, otherwise it doubles with every attempt, starting
+/// at `initial_backoff` and capped at `max_backoff`. If `Retry-After` asks
+/// for a longer wait than `max_backoff` the response is returned as is.
+///
+/// Only idempotent requests (`GET`, `HEAD`, `PUT`, `DELETE`, `OPTIONS` and
+/// `TRACE`) are retried by default, as a failed `POST` may still have been
+/// acted on by the instance.
+///
+/// ```
+/// # extern crate mammut;
+/// # extern crate reqwest;
+/// # fn main() -> mammut::Result<()> {
+/// # use mammut::testing::MockResponse;
+/// # use reqwest::{Method, StatusCode};
+/// # let server = mammut::testing::MockServer::start();
+/// # server.mock_once(Method::GET, "/api/v1/accounts/verify_credentials",
+/// #     MockResponse::new(StatusCode::SERVICE_UNAVAILABLE));
+/// # let data = server.data();
+/// use std::time::Duration;
+/// use mammut::Mastodon;
+/// use mammut::retry::RetryPolicy;
+///
+/// let policy = RetryPolicy::new()
+///     .max_attempts(5)
+///     .initial_backoff(Duration::from_millis(250));
+///
+/// let mastodon = Mastodon::from_data(data).retry_policy(policy);
+/// // Retried after the instance responds with `503 Service Unavailable`.
+/// mastodon.verify_credentials()?;
+/// # assert_eq!(server.requests().len(), 2);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// The total number of times a request is sent, including the first
+    /// attempt. `1` disables retries.
+    pub max_attempts: u32,
+    /// The delay before the first retry.
+    pub initial_backoff: Duration,
+    /// The longest delay between two attempts.
+    pub max_backoff: Duration,
+    /// Whether to randomise each backoff to between half and all of its
+    /// value, so that many clients don't retry in lockstep.
+    pub jitter: bool,
+    /// Whether to also retry requests that aren't idempotent, such as `POST`
+    /// and `PATCH`.
+    pub retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            jitter: true,
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The default policy: three attempts with jittered backoff starting at
+    /// half a second, for idempotent requests only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A policy that never retries.
+    pub fn never() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Set the total number of attempts.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Set the delay before the first retry.
+    pub fn initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Set the longest delay between two attempts.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Set whether backoffs are randomised.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set whether requests that aren't idempotent are retried.
+    pub fn retry_non_idempotent(mut self, retry: bool) -> Self {
+        self.retry_non_idempotent = retry;
+        self
+    }
+
+    /// How long to wait before retrying a request sent with `method` which
+    /// got `response` on its `attempt`th try, or `None` if it shouldn't be
+    /// retried.
+    pub(crate) fn delay(
+        &self,
+        method: &Method,
+        response: &Response,
+        attempt: u32,
+    ) -> Option<Duration> {
+        if attempt >= self.max_attempts || !is_retryable(response.status) {
+            return None;
+        }
+
+        if !self.retry_non_idempotent && !is_idempotent(method) {
+            return None;
+        }
+
+        if let Some(delay) = retry_after(response) {
+            return if delay <= self.max_backoff {
+                Some(delay)
+            } else {
+                None
+            };
+        }
+
+        Some(self.backoff(attempt))
+    }
+
+    /// The backoff after the `attempt`th failed try, doubling from
+    /// `initial_backoff` up to `max_backoff` and jittered if enabled.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let backoff = self
+            .initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| {
+                cmp::min(backoff, self.max_backoff)
+            });
+
+        if self.jitter {
+            let half = backoff / 2;
+            let nanos = half.as_nanos() as u64;
+            half + Duration::from_nanos(rand::thread_rng().gen_range(0, nanos + 1))
+        } else {
+            backoff
+        }
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE
+    )
+}
+
+// The delay asked for by the `Retry-After` header, either in seconds or as a
+// date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers.get(RETRY_AFTER)?;
+
+    match RetryAfter::parse_header(&value).ok()? {
+        RetryAfter::Delay(delay) => Some(delay),
+        RetryAfter::DateTime(date) => {
+            let date: SystemTime = date.into();
+            Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+        }
+    }
+}
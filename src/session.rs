@@ -0,0 +1,147 @@
+//! The state and logic shared by the blocking and async clients: the headers
+//! sent with each request, the rate limit, and when to retry.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::debug;
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::StatusCode;
+
+use crate::apps::Scope;
+use crate::transport::{Request, Response};
+use crate::{Data, Error, RateLimit, Result, RetryPolicy};
+
+#[derive(Clone, Debug)]
+pub(crate) struct Session {
+    pub(crate) headers: HeaderMap,
+    pub(crate) rate_limit: Arc<Mutex<Option<RateLimit>>>,
+    pub(crate) wait_for_rate_limit: bool,
+    pub(crate) retry: RetryPolicy,
+}
+
+impl Session {
+    // Fails with `Error::HeaderValue` if the access token can't be sent in a
+    // header, like `with_token`.
+    pub(crate) fn new(token: &str) -> Result<Self> {
+        let mut session = Session::anonymous();
+
+        if !token.is_empty() {
+            let auth = HeaderValue::from_str(&format!("Bearer {}", token))?;
+            session.headers.insert(header::AUTHORIZATION, auth);
+        }
+
+        Ok(session)
+    }
+
+    // A session without an access token, which can't fail to build.
+    pub(crate) fn anonymous() -> Self {
+        Session {
+            headers: HeaderMap::new(),
+            rate_limit: Arc::new(Mutex::new(None)),
+            wait_for_rate_limit: false,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    // A session for another access token, keeping the other headers and
+    // settings of this one but not its rate limit.
+    pub(crate) fn with_token(&self, token: &str) -> Result<Self> {
+        let auth = HeaderValue::from_str(&format!("Bearer {}", token))?;
+        let mut headers = self.headers.clone();
+        headers.insert(header::AUTHORIZATION, auth);
+
+        Ok(Session {
+            headers,
+            rate_limit: Arc::new(Mutex::new(None)),
+            ..self.clone()
+        })
+    }
+
+    pub(crate) fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().unwrap()
+    }
+
+    // Add the session's headers to `request`, unless it already has them.
+    pub(crate) fn prepare(&self, request: &mut Request) {
+        for (name, value) in &self.headers {
+            if !request.headers.contains_key(name) {
+                request.headers.insert(name.clone(), value.clone());
+            }
+        }
+    }
+
+    // How long to wait for the rate limit to reset before sending a request,
+    // if waiting for it is enabled and the budget has been used up.
+    pub(crate) fn wait_time(&self) -> Option<Duration> {
+        if !self.wait_for_rate_limit {
+            return None;
+        }
+
+        let wait = self.rate_limit().and_then(|limit| limit.wait_time())?;
+        debug!("RATE LIMITED: waiting {:?}", wait);
+        Some(wait)
+    }
+
+    // Record the rate limit from the `attempt`th response to `request`, and
+    // decide whether to send it again. Returns how long to wait first, or
+    // `None` if `response` is final.
+    pub(crate) fn retry_after(
+        &self,
+        request: &Request,
+        response: &Response,
+        attempt: &mut u32,
+    ) -> Option<Duration> {
+        let rate_limit = RateLimit::from_headers(&response.headers);
+        if rate_limit.is_some() {
+            *self.rate_limit.lock().unwrap() = rate_limit;
+        }
+
+        // Waiting for the rate limit counts as an attempt, so an instance
+        // that keeps rejecting the request can't make it wait forever.
+        let is_rate_limited = response.status == StatusCode::TOO_MANY_REQUESTS;
+        if self.wait_for_rate_limit && is_rate_limited && *attempt < self.retry.max_attempts {
+            if let Some(wait) = rate_limit.and_then(|limit| limit.wait_time()) {
+                debug!("RATE LIMITED: waiting {:?}", wait);
+                *attempt += 1;
+                return Some(wait);
+            }
+        }
+
+        let delay = self.retry.delay(&request.method, response, *attempt)?;
+        debug!("RETRYING: {} after {:?}", response.status, delay);
+        *attempt += 1;
+        Some(delay)
+    }
+}
+
+impl Data {
+    // Where to revoke the access token, the advertised url if there is one.
+    pub(crate) fn revocation_url(&self) -> String {
+        match self.revocation_endpoint {
+            Some(ref url) => url.to_string(),
+            None => format!("{}/oauth/revoke", self.base),
+        }
+    }
+
+    // Fail with `Error::AccessTokenRequired` if there's no access token.
+    pub(crate) fn require_token(&self) -> Result<()> {
+        if self.token.is_empty() {
+            Err(Error::AccessTokenRequired)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Also fail with `Error::InsufficientScope` if the access token is known
+    // not to have been granted `scope`.
+    pub(crate) fn require_scope(&self, scope: &'static str) -> Result<()> {
+        self.require_token()?;
+
+        let scope = Scope::new(scope);
+        match self.scopes {
+            Some(ref granted) if !granted.contains(&scope) => Err(Error::InsufficientScope(scope)),
+            _ => Ok(()),
+        }
+    }
+}
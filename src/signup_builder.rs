@@ -0,0 +1,77 @@
+use std::fmt;
+
+use crate::redact;
+
+/// A builder pattern struct for signing up a new account on the instance,
+/// with `Mastodon::register_account`.
+/// ```
+/// use mammut::SignupBuilder;
+///
+/// let signup = SignupBuilder::new("alice".into(), "alice@example.com".into(), "hunter22".into())
+///     .agreement(true)
+///     .locale("en".into());
+/// ```
+#[derive(Default, Clone, Serialize)]
+pub struct SignupBuilder {
+    /// The username of the new account.
+    pub username: String,
+    /// The email address the account is confirmed with.
+    pub email: String,
+    /// The password of the new account.
+    pub password: String,
+    /// Whether the user agrees to the instance's rules and terms of service.
+    /// The instance refuses the sign up unless this is `true`.
+    pub agreement: bool,
+    /// The language of the confirmation email, e.g. `en`.
+    pub locale: String,
+    /// Why the user wants to join, for instances that approve sign ups by
+    /// hand.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl SignupBuilder {
+    /// Create a new sign up, with an English confirmation email and without
+    /// agreeing to the instance's terms yet.
+    pub fn new(username: String, email: String, password: String) -> Self {
+        SignupBuilder {
+            username,
+            email,
+            password,
+            locale: "en".into(),
+            ..Self::default()
+        }
+    }
+
+    /// Set whether the user agrees to the instance's rules and terms of
+    /// service.
+    pub fn agreement(mut self, agreement: bool) -> Self {
+        self.agreement = agreement;
+        self
+    }
+
+    /// Set the language of the confirmation email.
+    pub fn locale(mut self, locale: String) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// Set why the user wants to join.
+    pub fn reason(mut self, reason: String) -> Self {
+        self.reason = Some(reason);
+        self
+    }
+}
+
+impl fmt::Debug for SignupBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SignupBuilder")
+            .field("username", &self.username)
+            .field("email", &self.email)
+            .field("password", &redact::Secret(&self.password))
+            .field("agreement", &self.agreement)
+            .field("locale", &self.locale)
+            .field("reason", &self.reason)
+            .finish()
+    }
+}
@@ -47,7 +47,7 @@ impl StatusBuilder {
     /// ```
     pub fn new(status: String) -> Self {
         StatusBuilder {
-            status: status,
+            status,
             ..Self::default()
         }
     }
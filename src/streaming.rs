@@ -0,0 +1,845 @@
+use std::cmp::Ordering;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+use std::mem;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use log::debug;
+use reqwest::header::{HeaderValue, ACCEPT};
+use reqwest::Method;
+use serde::Deserialize;
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+use url::Url;
+
+use crate::entities::notification::Notification;
+use crate::entities::status::Status;
+use crate::retry::RetryPolicy;
+use crate::transport::{Request, Response};
+use crate::{check_status, Error, Mastodon, Result};
+
+/// How long a connection may go without sending anything, not even a
+/// heartbeat, before it is considered dead. Mastodon sends a heartbeat every
+/// 15 seconds.
+pub(crate) const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A stream of events offered by the streaming API.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Stream {
+    /// Statuses and notifications for the authorised user.
+    User,
+    /// Notifications for the authorised user.
+    UserNotification,
+    /// All public statuses known to the instance.
+    Public,
+    /// Public statuses posted on the instance.
+    PublicLocal,
+    /// Public statuses posted on other instances.
+    PublicRemote,
+    /// Public statuses with the hashtag, without the leading `#`.
+    Hashtag(String),
+    /// Public statuses posted on the instance with the hashtag.
+    HashtagLocal(String),
+    /// Statuses in the list with the id.
+    List(String),
+    /// Direct messages to and from the authorised user.
+    Direct,
+}
+
+impl Stream {
+    /// The name of the stream, e.g. `public:local`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Stream::User => "user",
+            Stream::UserNotification => "user:notification",
+            Stream::Public => "public",
+            Stream::PublicLocal => "public:local",
+            Stream::PublicRemote => "public:remote",
+            Stream::Hashtag(_) => "hashtag",
+            Stream::HashtagLocal(_) => "hashtag:local",
+            Stream::List(_) => "list",
+            Stream::Direct => "direct",
+        }
+    }
+
+    // The parameter selecting the hashtag or list, if the stream has one.
+    pub(crate) fn param(&self) -> Option<(&'static str, &str)> {
+        match *self {
+            Stream::Hashtag(ref tag) | Stream::HashtagLocal(ref tag) => Some(("tag", tag)),
+            Stream::List(ref id) => Some(("list", id)),
+            _ => None,
+        }
+    }
+
+    // The stream named by the `stream` field of a WebSocket event, e.g.
+    // `["hashtag", "rust"]`.
+    #[cfg(feature = "websocket")]
+    pub(crate) fn from_parts(parts: &[String]) -> Option<Stream> {
+        let param = parts.get(1).cloned();
+
+        Some(match (parts.first()?.as_str(), param) {
+            ("user", None) => Stream::User,
+            ("user:notification", None) => Stream::UserNotification,
+            ("public", None) => Stream::Public,
+            ("public:local", None) => Stream::PublicLocal,
+            ("public:remote", None) => Stream::PublicRemote,
+            ("hashtag", Some(tag)) => Stream::Hashtag(tag),
+            ("hashtag:local", Some(tag)) => Stream::HashtagLocal(tag),
+            ("list", Some(id)) => Stream::List(id),
+            ("direct", None) => Stream::Direct,
+            _ => return None,
+        })
+    }
+
+    // The scope the access token needs to subscribe to the stream, if any.
+    pub(crate) fn scope(&self) -> Option<&'static str> {
+        match *self {
+            Stream::User | Stream::List(_) | Stream::Direct => Some("read:statuses"),
+            Stream::UserNotification => Some("read:notifications"),
+            _ => None,
+        }
+    }
+
+    // The url of the stream's server-sent events endpoint under `base`.
+    fn url(&self, base: &Url) -> Result<Url> {
+        let path = format!("/api/v1/streaming/{}", self.name().replace(':', "/"));
+        let mut url = base.join(&path)?;
+
+        if let Some((name, value)) = self.param() {
+            url.query_pairs_mut().append_pair(name, value);
+        }
+
+        Ok(url)
+    }
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.param() {
+            Some((_, value)) => write!(f, "{} {}", self.name(), value),
+            None => f.write_str(self.name()),
+        }
+    }
+}
+
+/// An event received from the streaming API.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// A new status.
+    Update(Box<Status>),
+    /// A new notification.
+    Notification(Box<Notification>),
+    /// The status with the id was deleted.
+    Delete(String),
+    /// A status was edited.
+    StatusUpdate(Box<Status>),
+    /// The user's filters changed, and should be fetched again.
+    FiltersChanged,
+    /// An event this version of mammut doesn't know about.
+    Unknown {
+        /// The name of the event.
+        event: String,
+        /// The event's payload, as sent by the instance.
+        payload: String,
+    },
+}
+
+impl Event {
+    /// Decode an event from its name and payload, as sent by the streaming
+    /// API.
+    ///
+    /// ```
+    /// use mammut::streaming::Event;
+    ///
+    /// match Event::from_parts("delete", "103270115826048975")? {
+    ///     Event::Delete(id) => assert_eq!(id, "103270115826048975"),
+    ///     event => panic!("unexpected event {:?}", event),
+    /// }
+    /// # Ok::<(), mammut::Error>(())
+    /// ```
+    pub fn from_parts(event: &str, payload: &str) -> Result<Event> {
+        Ok(match event {
+            "update" => Event::Update(json::from_str(payload)?),
+            "notification" => Event::Notification(json::from_str(payload)?),
+            "delete" => Event::Delete(payload.to_owned()),
+            "status.update" => Event::StatusUpdate(json::from_str(payload)?),
+            "filters_changed" => Event::FiltersChanged,
+            _ => Event::Unknown {
+                event: event.to_owned(),
+                payload: payload.to_owned(),
+            },
+        })
+    }
+}
+
+/// A blocking iterator over the events of a `Stream`, received as
+/// server-sent events. Created with `Mastodon::stream`.
+///
+/// The connection is opened on the first call to `next`. When it drops, or
+/// sends nothing for longer than the heartbeat timeout, the stream reconnects
+/// with the backoff of its reconnect policy. If every attempt allowed by the
+/// policy fails the error is returned, and the next call to `next` starts
+/// reconnecting again. Errors the instance won't recover from, such as
+/// `401 Unauthorized`, are returned without retrying.
+///
+/// The stream is read through the transport of the `Mastodon` it was created
+/// from, see `Transport::send_streaming`, so it uses the proxies, connect
+/// timeout and user agent set on its `MastodonBuilder`, but not the builder's
+/// request timeout. A connection that is given up on is closed once a read
+/// from it times out in the transport, which for clients made by the builder
+/// is after the default heartbeat timeout, and for `reqwest::Client` after 30
+/// seconds unless it was built with another timeout.
+///
+/// ```
+/// # extern crate mammut;
+/// # fn main() -> mammut::Result<()> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let mastodon = server.mastodon();
+/// use mammut::streaming::{Event, Stream};
+///
+/// for event in mastodon.stream(Stream::User)?.take(3) {
+///     match event? {
+///         Event::Update(status) => println!("{}", status.content),
+///         Event::Notification(notification) => println!("{:?}", notification.notification_type),
+///         Event::Delete(id) => println!("{} was deleted", id),
+///         _ => {}
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct EventStream {
+    stream: Stream,
+    url: Url,
+    mastodon: Mastodon,
+    reconnect: RetryPolicy,
+    heartbeat_timeout: Duration,
+    lines: Option<Receiver<io::Result<String>>>,
+    failures: u32,
+    connections: u64,
+}
+
+impl EventStream {
+    pub(crate) fn new(mastodon: &Mastodon, stream: Stream) -> Result<Self> {
+        if let Some(scope) = stream.scope() {
+            mastodon.require_scope(scope)?;
+        }
+
+        let base = Url::parse(&mastodon.base)?;
+
+        Ok(EventStream {
+            url: stream.url(&base)?,
+            stream,
+            mastodon: mastodon.clone(),
+            reconnect: mastodon.session.retry,
+            heartbeat_timeout: HEARTBEAT_TIMEOUT,
+            lines: None,
+            failures: 0,
+            connections: 0,
+        })
+    }
+
+    /// Connect to the streaming API at `url` instead of the instance's base
+    /// url, e.g. the `streaming_api` url from `Mastodon::instance`. `ws://`
+    /// and `wss://` urls are connected to over `http://` and `https://`.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # fn main() -> mammut::Result<()> {
+    /// # let server = mammut::testing::MockServer::start();
+    /// # let mastodon = server.mastodon();
+    /// use mammut::streaming::Stream;
+    ///
+    /// let instance = mastodon.instance()?;
+    /// let mut stream = mastodon.stream(Stream::Public)?;
+    /// if let Some(urls) = instance.urls {
+    ///     stream = stream.streaming_api(&urls.streaming_api)?;
+    /// }
+    /// # assert!(stream.next().is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn streaming_api(mut self, url: &str) -> Result<Self> {
+        let base = match Url::parse(url)? {
+            ref url if url.scheme() == "ws" => with_scheme(url, "http")?,
+            ref url if url.scheme() == "wss" => with_scheme(url, "https")?,
+            url => url,
+        };
+
+        self.url = self.stream.url(&base)?;
+        self.lines = None;
+        Ok(self)
+    }
+
+    /// Set how long the connection may go without sending anything before
+    /// reconnecting. Defaults to 60 seconds.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # extern crate reqwest;
+    /// # fn main() -> mammut::Result<()> {
+    /// use std::time::Duration;
+    /// use mammut::streaming::{Event, Stream};
+    /// use mammut::testing::{MockResponse, MockServer};
+    /// use reqwest::Method;
+    ///
+    /// let server = MockServer::start();
+    /// let path = "/api/v1/streaming/public";
+    /// server.mock_once(Method::GET, path, MockResponse::event_stream(vec![]).stall());
+    ///
+    /// let mut stream = server
+    ///     .mastodon()
+    ///     .stream(Stream::Public)?
+    ///     .heartbeat_timeout(Duration::from_millis(200));
+    ///
+    /// // The first connection never sends an event, so the stream gives up on
+    /// // it and reconnects.
+    /// match stream.next().unwrap()? {
+    ///     Event::Update(_) => {}
+    ///     event => panic!("unexpected event {:?}", event),
+    /// }
+    /// assert_eq!(stream.connections(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Set how the stream backs off between reconnects, and how many failed
+    /// attempts in a row it makes before returning the error. Defaults to the
+    /// retry policy of the `Mastodon` the stream was created from.
+    pub fn reconnect_policy(mut self, policy: RetryPolicy) -> Self {
+        self.reconnect = policy;
+        self
+    }
+
+    /// The stream the events are from.
+    pub fn stream(&self) -> &Stream {
+        &self.stream
+    }
+
+    /// The number of times the stream has connected so far. Events sent
+    /// while the stream was reconnecting are missed, so this can be used to
+    /// tell when to fetch them with the REST API.
+    pub fn connections(&self) -> u64 {
+        self.connections
+    }
+
+    // Connect if not already connected, backing off after failed attempts.
+    // Returns whether a new connection was opened.
+    pub(crate) fn ensure_connected(&mut self) -> Result<bool> {
+        while self.lines.is_none() {
+            if self.failures > 0 {
+                let delay = self.reconnect.backoff(self.failures);
+                debug!("STREAM RECONNECTING: {} after {:?}", self.stream, delay);
+                thread::sleep(delay);
+            }
+
+            match self.connect() {
+                Ok(lines) => {
+                    debug!("STREAM CONNECTED: {}", self.stream);
+                    self.lines = Some(lines);
+                    self.connections += 1;
+                    return Ok(true);
+                }
+                Err(error) => {
+                    self.failures += 1;
+                    if is_fatal(&error) || self.failures >= self.reconnect.max_attempts {
+                        self.failures = 0;
+                        return Err(error);
+                    }
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    // Read the next event, or `None` if the connection dropped.
+    pub(crate) fn read_next(&mut self) -> Option<Result<Event>> {
+        match self.read_event() {
+            Ok(Some((event, data))) => {
+                self.failures = 0;
+                return Some(Event::from_parts(&event, &data));
+            }
+            Ok(None) => debug!("STREAM CLOSED: {}", self.stream),
+            Err(error) => debug!("STREAM DROPPED: {}: {}", self.stream, error),
+        }
+
+        self.lines = None;
+        self.failures += 1;
+        None
+    }
+
+    // Open the connection, returning the lines of the response as they're
+    // read by another thread, so that waiting for them can time out.
+    fn connect(&mut self) -> Result<Receiver<io::Result<String>>> {
+        let mut request = Request::get(self.url.as_str())?;
+        request
+            .headers
+            .insert(ACCEPT, HeaderValue::from_static("text/event-stream"));
+        self.mastodon.session.prepare(&mut request);
+
+        let mut response = self.mastodon.transport.send_streaming(request)?;
+
+        if !response.status.is_success() {
+            let mut body = Vec::new();
+            response.body.read_to_end(&mut body)?;
+            check_status(
+                &Method::GET,
+                &self.url,
+                Response {
+                    status: response.status,
+                    headers: response.headers,
+                    body,
+                },
+            )?;
+        }
+
+        // The thread stops at the next line after the stream has hung up, or
+        // once a read fails, which the transport should make happen by timing
+        // reads out if the connection goes quiet.
+        let (sender, receiver) = mpsc::sync_channel(64);
+        let mut reader = BufReader::new(response.body);
+        thread::spawn(move || loop {
+            let mut line = String::new();
+            let line = match reader.read_line(&mut line) {
+                Ok(0) => return,
+                Ok(_) => Ok(line),
+                Err(error) => Err(error),
+            };
+            let failed = line.is_err();
+
+            if sender.send(line).is_err() || failed {
+                return;
+            }
+        });
+
+        Ok(receiver)
+    }
+
+    // Read until the next event is dispatched, returning its name and data,
+    // or `None` if the connection was closed.
+    fn read_event(&mut self) -> io::Result<Option<(String, String)>> {
+        let lines = match self.lines {
+            Some(ref lines) => lines,
+            None => return Ok(None),
+        };
+        let mut event = String::new();
+        let mut data: Option<String> = None;
+
+        loop {
+            let line = match lines.recv_timeout(self.heartbeat_timeout) {
+                Ok(line) => line?,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "missed heartbeat"));
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(None),
+            };
+
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                match data.take() {
+                    Some(data) => return Ok(Some((event, data))),
+                    None => event.clear(),
+                }
+                continue;
+            }
+
+            // Comments are sent as heartbeats, which only need to reset the
+            // timeout.
+            if line.starts_with(':') {
+                continue;
+            }
+
+            let (field, value) = match line.find(':') {
+                Some(index) => {
+                    let value = &line[index + 1..];
+                    (&line[..index], value.strip_prefix(' ').unwrap_or(value))
+                }
+                None => (line, ""),
+            };
+
+            match field {
+                "event" => event = value.to_owned(),
+                "data" => match data {
+                    Some(ref mut data) => {
+                        data.push('\n');
+                        data.push_str(value);
+                    }
+                    None => data = Some(value.to_owned()),
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Iterator for EventStream {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Err(error) = self.ensure_connected() {
+                return Some(Err(error));
+            }
+
+            if let Some(event) = self.read_next() {
+                return Some(event);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventStream")
+            .field("stream", &self.stream)
+            .field("url", &self.url)
+            .field("reconnect", &self.reconnect)
+            .field("heartbeat_timeout", &self.heartbeat_timeout)
+            .field("connected", &self.lines.is_some())
+            .field("connections", &self.connections)
+            .finish()
+    }
+}
+
+// `url` with its scheme replaced. `Url::set_scheme` refuses to switch
+// between `http` and `ws`, so the url is parsed again instead.
+pub(crate) fn with_scheme(url: &Url, scheme: &str) -> Result<Url> {
+    Ok(Url::parse(&format!(
+        "{}{}",
+        scheme,
+        &url[url::Position::AfterScheme..]
+    ))?)
+}
+
+// Whether reconnecting can't fix the error, e.g. an invalid access token.
+fn is_fatal(error: &Error) -> bool {
+    match *error {
+        Error::Response(ref response) => {
+            response.status.is_client_error() && response.status.as_u16() != 429
+        }
+        Error::Url(_) => true,
+        _ => false,
+    }
+}
+
+/// Follows a `Stream` without missing events. Created with
+/// `EventStream::follow`.
+///
+/// The follower remembers the newest status and notification it returned.
+/// Whenever the stream connects, it first fetches anything newer from the
+/// matching REST routes, e.g. the home timeline and notifications for
+/// `Stream::User`, so the events sent while it was disconnected aren't lost.
+/// It pages through the routes until it has caught up, however long it was
+/// disconnected. Backfilled statuses and notifications are returned oldest
+/// first, before the live events, and none are returned twice.
+///
+/// To pick up where an earlier follower left off, save its
+/// `last_status_id` and `last_notification_id` and pass them to
+/// `since_status` and `since_notification`.
+///
+/// ```
+/// # extern crate mammut;
+/// # fn main() -> mammut::Result<()> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let mastodon = server.mastodon();
+/// # let policy = mammut::RetryPolicy::new().initial_backoff(std::time::Duration::from_millis(10));
+/// use mammut::streaming::{Event, Stream};
+///
+/// let follower = mastodon
+///     .stream(Stream::User)?
+/// #   .reconnect_policy(policy)
+///     .follow()
+///     .since_status("1");
+///
+/// let events = follower
+///     .take(6)
+///     .map(|event| match event? {
+///         Event::Update(status) => Ok(format!("update {}", status.id)),
+///         Event::Notification(notification) => Ok(format!("notification {}", notification.id)),
+///         Event::Delete(id) => Ok(format!("delete {}", id)),
+///         _ => Ok("other".into()),
+///     })
+///     .collect::<mammut::Result<Vec<_>>>()?;
+///
+/// // Statuses 2 and 3 are backfilled from the home timeline. After the
+/// // connection drops the stream sends status 4 and its notification again,
+/// // and they're skipped.
+/// assert_eq!(
+///     events,
+///     [
+///         "update 2",
+///         "update 3",
+///         "update 4",
+///         "notification 4",
+///         "delete 1",
+///         "delete 1",
+///     ]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Follower {
+    events: EventStream,
+    last_status: Option<String>,
+    last_notification: Option<String>,
+    backfilled: VecDeque<Event>,
+    backfill_pending: bool,
+    seen: VecDeque<(Kind, String)>,
+    seen_set: HashSet<(Kind, String)>,
+}
+
+/// How many of the latest status and notification ids are remembered to skip
+/// duplicates.
+const SEEN_CAPACITY: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Kind {
+    Status,
+    Notification,
+}
+
+impl EventStream {
+    /// Follow the stream without missing events, backfilling through the
+    /// REST routes after every reconnect. See `Follower`.
+    pub fn follow(self) -> Follower {
+        Follower {
+            events: self,
+            last_status: None,
+            last_notification: None,
+            backfilled: VecDeque::new(),
+            backfill_pending: false,
+            seen: VecDeque::new(),
+            seen_set: HashSet::new(),
+        }
+    }
+}
+
+impl Follower {
+    /// Backfill statuses newer than `id` when the stream first connects.
+    pub fn since_status<I: Into<String>>(mut self, id: I) -> Self {
+        self.last_status = Some(id.into());
+        self
+    }
+
+    /// Backfill notifications newer than `id` when the stream first
+    /// connects.
+    pub fn since_notification<I: Into<String>>(mut self, id: I) -> Self {
+        self.last_notification = Some(id.into());
+        self
+    }
+
+    /// The id of the newest status returned so far.
+    pub fn last_status_id(&self) -> Option<&str> {
+        self.last_status.as_deref()
+    }
+
+    /// The id of the newest notification returned so far.
+    pub fn last_notification_id(&self) -> Option<&str> {
+        self.last_notification.as_deref()
+    }
+
+    /// The underlying stream of live events.
+    pub fn events(&self) -> &EventStream {
+        &self.events
+    }
+
+    // Fetch everything newer than the last status and notification, oldest
+    // first.
+    fn backfill(&mut self) -> Result<()> {
+        let stream = self.events.stream.clone();
+        let mut events = Vec::new();
+
+        if let (Some((path, query)), Some(since)) =
+            (status_route(&stream), self.last_status.clone())
+        {
+            let statuses: Vec<Status> =
+                self.fetch_newer(Kind::Status, &path, &query, &since, |s: &Status| &s.id)?;
+            events.extend(
+                statuses
+                    .into_iter()
+                    .map(|s| (s.created_at, Event::Update(Box::new(s)))),
+            );
+        }
+
+        let notifications = match stream {
+            Stream::User | Stream::UserNotification => self.last_notification.clone(),
+            _ => None,
+        };
+        if let Some(since) = notifications {
+            let notifications: Vec<Notification> = self.fetch_newer(
+                Kind::Notification,
+                "/api/v1/notifications",
+                &[],
+                &since,
+                |n: &Notification| &n.id,
+            )?;
+            events.extend(
+                notifications
+                    .into_iter()
+                    .map(|n| (n.created_at, Event::Notification(Box::new(n)))),
+            );
+        }
+
+        // Sorting is stable, so each kind stays ordered by id.
+        events.sort_by_key(|&(created_at, _)| created_at);
+        self.backfilled = events.into_iter().map(|(_, event)| event).collect();
+        Ok(())
+    }
+
+    // Page through `path` with `min_id`, returning the entities newer than
+    // `since` that haven't been returned yet, oldest first.
+    fn fetch_newer<T>(
+        &self,
+        kind: Kind,
+        path: &str,
+        query: &[(&str, String)],
+        since: &str,
+        id: fn(&T) -> &String,
+    ) -> Result<Vec<T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let mastodon = &self.events.mastodon;
+        let mut page = mastodon
+            .request(Method::GET, path)
+            .query(query)
+            .query(&[("min_id", since), ("limit", "40")])
+            .paged::<T>()?;
+        let mut items = mem::take(&mut page.initial_items);
+        let mut newer = Vec::new();
+
+        // Keep paging until a page has nothing new, so nothing is skipped
+        // however long the stream was disconnected.
+        loop {
+            // Each page is newest first.
+            let before = newer.len();
+            newer.extend(items.into_iter().rev().filter(|item| {
+                is_newer(id(item), since) && !self.seen_set.contains(&(kind, id(item).clone()))
+            }));
+            if newer.len() == before {
+                break;
+            }
+
+            items = match page.prev_page()? {
+                Some(items) => items,
+                None => break,
+            };
+        }
+
+        newer.sort_by(|a, b| compare_ids(id(a), id(b)));
+        newer.dedup_by(|a, b| id(a) == id(b));
+        Ok(newer)
+    }
+
+    // Record the event as returned, or return `None` if it already was.
+    fn accept(&mut self, event: Event) -> Option<Event> {
+        let (kind, id) = match event {
+            Event::Update(ref status) => (Kind::Status, status.id.clone()),
+            Event::Notification(ref notification) => (Kind::Notification, notification.id.clone()),
+            _ => return Some(event),
+        };
+
+        if !self.seen_set.insert((kind, id.clone())) {
+            return None;
+        }
+        self.seen.push_back((kind, id.clone()));
+        if self.seen.len() > SEEN_CAPACITY {
+            if let Some(oldest) = self.seen.pop_front() {
+                self.seen_set.remove(&oldest);
+            }
+        }
+
+        let last = match kind {
+            Kind::Status => &mut self.last_status,
+            Kind::Notification => &mut self.last_notification,
+        };
+        if last.as_ref().map_or(true, |last| is_newer(&id, last)) {
+            *last = Some(id);
+        }
+
+        Some(event)
+    }
+}
+
+impl Iterator for Follower {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while let Some(event) = self.backfilled.pop_front() {
+                if let Some(event) = self.accept(event) {
+                    return Some(Ok(event));
+                }
+            }
+
+            match self.events.ensure_connected() {
+                Ok(true) => self.backfill_pending = true,
+                Ok(false) => {}
+                Err(error) => return Some(Err(error)),
+            }
+
+            // A failed backfill is tried again on the next call, before any
+            // more live events are returned.
+            if self.backfill_pending {
+                if let Err(error) = self.backfill() {
+                    return Some(Err(error));
+                }
+                self.backfill_pending = false;
+                continue;
+            }
+
+            match self.events.read_next() {
+                Some(Ok(event)) => {
+                    if let Some(event) = self.accept(event) {
+                        return Some(Ok(event));
+                    }
+                }
+                Some(Err(error)) => return Some(Err(error)),
+                None => {}
+            }
+        }
+    }
+}
+
+// The REST route with the statuses of `stream`, and its query.
+fn status_route(stream: &Stream) -> Option<(String, Vec<(&'static str, String)>)> {
+    let local = || vec![("local", "true".to_owned())];
+
+    Some(match *stream {
+        Stream::User => ("/api/v1/timelines/home".into(), vec![]),
+        Stream::Public => ("/api/v1/timelines/public".into(), vec![]),
+        Stream::PublicLocal => ("/api/v1/timelines/public".into(), local()),
+        Stream::PublicRemote => (
+            "/api/v1/timelines/public".into(),
+            vec![("remote", "true".to_owned())],
+        ),
+        Stream::Hashtag(ref tag) => (format!("/api/v1/timelines/tag/{}", encode(tag)), vec![]),
+        Stream::HashtagLocal(ref tag) => {
+            (format!("/api/v1/timelines/tag/{}", encode(tag)), local())
+        }
+        Stream::List(ref id) => (format!("/api/v1/timelines/list/{}", encode(id)), vec![]),
+        Stream::Direct => ("/api/v1/timelines/direct".into(), vec![]),
+        Stream::UserNotification => return None,
+    })
+}
+
+fn encode(segment: &str) -> String {
+    utf8_percent_encode(segment, PATH_SEGMENT_ENCODE_SET).to_string()
+}
+
+// Ids are numeric strings that grow over time, so a longer id is newer.
+fn compare_ids(a: &str, b: &str) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+fn is_newer(id: &str, than: &str) -> bool {
+    compare_ids(id, than) == Ordering::Greater
+}
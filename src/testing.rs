@@ -0,0 +1,823 @@
+//! A local stand-in for a Mastodon instance, for testing code built on
+//! mammut without network access. Requires the `testing` feature.
+//!
+//! `MockServer` listens on a random port on `127.0.0.1`, and serves canned
+//! JSON for every route `Mastodon` and `Registration` know about. Paginated
+//! routes serve three items, newest first, two to a page with the same `Link`
+//! headers Mastodon sends. Every request the server receives is recorded, and
+//! any route can be overridden with `MockServer::mock`. The streaming routes
+//! send a few events and close, and with the `websocket` feature the server
+//! answers each WebSocket subscription with an event for the stream.
+//!
+//! ```
+//! # extern crate mammut;
+//! use mammut::testing::MockServer;
+//!
+//! # fn main() -> mammut::Result<()> {
+//! let server = MockServer::start();
+//! let mastodon = server.mastodon();
+//!
+//! let statuses: Vec<_> = mastodon.get_home_timeline()?.items_iter().collect();
+//! assert_eq!(statuses.len(), 3);
+//!
+//! let requests = server.requests();
+//! assert_eq!(requests.len(), 2);
+//! assert_eq!(requests[1].path, "/api/v1/timelines/home");
+//! assert_eq!(requests[1].query.as_ref().unwrap(), "max_id=2");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::prelude::*;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+
+use crate::{Data, Mastodon, Registration, Result};
+
+/// The client id handed out by the mock server's `/api/v1/apps` route.
+pub const CLIENT_ID: &str = "mock-client-id";
+/// The client secret handed out by the mock server's `/api/v1/apps` route.
+pub const CLIENT_SECRET: &str = "mock-client-secret";
+/// The access token handed out by the mock server's `/oauth/token` route.
+pub const ACCESS_TOKEN: &str = "mock-access-token";
+/// The `X-RateLimit-Limit` the mock server reports. Each request the server
+/// receives uses up one request of the budget, unless the response was mocked
+/// with its own `X-RateLimit-*` headers.
+pub const RATE_LIMIT: u64 = 300;
+
+/// A local HTTP server that imitates a Mastodon instance. The server stops
+/// when it is dropped.
+pub struct MockServer {
+    base: String,
+    state: Arc<Mutex<State>>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct State {
+    requests: Vec<RecordedRequest>,
+    mocks: Vec<Mock>,
+}
+
+struct Mock {
+    method: Method,
+    path: String,
+    response: MockResponse,
+    once: bool,
+}
+
+/// A request received by a `MockServer`.
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    /// The HTTP method of the request.
+    pub method: Method,
+    /// The path of the request, without the query string.
+    pub path: String,
+    /// The query string of the request, if any.
+    pub query: Option<String>,
+    /// The request's headers.
+    pub headers: HeaderMap,
+    /// The request body.
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    /// The request body as a string, with invalid UTF-8 replaced.
+    pub fn body_string(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// Deserialise the request body from JSON.
+    pub fn json<T: for<'de> Deserialize<'de>>(&self) -> Result<T> {
+        Ok(json::from_slice(&self.body)?)
+    }
+
+    /// The decoded value of the query parameter `name`, if present.
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        let query = self.query.as_ref()?;
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    /// The decoded value of the field `name` of a url encoded form body, if
+    /// present.
+    pub fn form_param(&self, name: &str) -> Option<String> {
+        url::form_urlencoded::parse(&self.body)
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+}
+
+/// A canned response served by a `MockServer`.
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    /// The status code of the response.
+    pub status: StatusCode,
+    /// The response's headers.
+    pub headers: Vec<(String, String)>,
+    /// The response body.
+    pub body: Vec<u8>,
+    /// Whether the connection is kept open after the body, see
+    /// `MockResponse::stall`.
+    pub stall: bool,
+}
+
+impl MockResponse {
+    /// A response with `status` and an empty body.
+    pub fn new(status: StatusCode) -> Self {
+        MockResponse {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            stall: false,
+        }
+    }
+
+    /// A response with `status` and `body` as its JSON body.
+    pub fn json(status: StatusCode, body: &json::Value) -> Self {
+        MockResponse::new(status)
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+    }
+
+    /// A `text/event-stream` response sending each `(event, data)` pair as a
+    /// server-sent event, as the streaming API does. The connection closes
+    /// after the last event.
+    ///
+    /// ```
+    /// # extern crate mammut;
+    /// # extern crate reqwest;
+    /// # fn main() -> mammut::Result<()> {
+    /// use std::time::Duration;
+    /// use mammut::RetryPolicy;
+    /// use mammut::streaming::{Event, Stream};
+    /// use mammut::testing::{MockResponse, MockServer};
+    /// use reqwest::{Method, StatusCode};
+    ///
+    /// let server = MockServer::start();
+    /// let path = "/api/v1/streaming/hashtag";
+    /// server.mock_once(Method::GET, path, MockResponse::new(StatusCode::BAD_GATEWAY));
+    /// server.mock_once(Method::GET, path, MockResponse::event_stream(vec![("delete", "2")]));
+    ///
+    /// let policy = RetryPolicy::new().initial_backoff(Duration::from_millis(10));
+    /// let mut stream = server
+    ///     .mastodon()
+    ///     .stream(Stream::Hashtag("mammut".into()))?
+    ///     .reconnect_policy(policy);
+    ///
+    /// match stream.next().unwrap()? {
+    ///     Event::Delete(id) => assert_eq!(id, "2"),
+    ///     event => panic!("unexpected event {:?}", event),
+    /// }
+    /// assert_eq!(server.requests()[0].query_param("tag").unwrap(), "mammut");
+    ///
+    /// // The connection closed after the event, so the stream reconnects.
+    /// assert!(stream.next().unwrap().is_ok());
+    /// assert_eq!(stream.connections(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn event_stream<'a, I>(events: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let mut body = String::from(":)\n\n");
+        for (event, data) in events {
+            body += &format!("event: {}\n", event);
+            for line in data.lines() {
+                body += &format!("data: {}\n", line);
+            }
+            body += "\n";
+        }
+
+        MockResponse::new(StatusCode::OK)
+            .header("Content-Type", "text/event-stream")
+            .body(body)
+    }
+
+    /// Add a header to the response.
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the body of the response.
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Keep the connection open after sending the body, without sending
+    /// anything more until the server stops, like a connection that has hung
+    /// up without being closed. See `EventStream::heartbeat_timeout` for an
+    /// example.
+    pub fn stall(mut self) -> Self {
+        self.stall = true;
+        self
+    }
+}
+
+impl MockServer {
+    /// Start a server on a random port.
+    ///
+    /// # Panics
+    /// If no port could be bound.
+    pub fn start() -> Self {
+        let server = tiny_http::Server::http("127.0.0.1:0").expect("couldn't start mock server");
+        let base = format!("http://{}", server.server_addr());
+        let state = Arc::new(Mutex::new(State::default()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread = {
+            let base = base.clone();
+            let state = state.clone();
+            let running = running.clone();
+
+            thread::spawn(move || {
+                while running.load(Ordering::SeqCst) {
+                    if let Ok(Some(request)) = server.recv_timeout(Duration::from_millis(50)) {
+                        handle(&base, &state, &running, request);
+                    }
+                }
+            })
+        };
+
+        MockServer {
+            base,
+            state,
+            running,
+            thread: Some(thread),
+        }
+    }
+
+    /// The base url of the server, eg. `http://127.0.0.1:4000`.
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    /// `Data` for an app that is already registered and authorised with the
+    /// server.
+    pub fn data(&self) -> Data {
+        Data {
+            base: self.base.clone().into(),
+            client_id: CLIENT_ID.into(),
+            client_secret: CLIENT_SECRET.into(),
+            redirect: "urn:ietf:wg:oauth:2.0:oob".into(),
+            token: ACCESS_TOKEN.into(),
+            scopes: None,
+            revocation_endpoint: None,
+        }
+    }
+
+    /// A client authorised with the server.
+    pub fn mastodon(&self) -> Mastodon {
+        Mastodon::from_data(self.data())
+    }
+
+    /// A new registration process with the server.
+    pub fn registration(&self) -> Registration {
+        Registration::new(self.base.clone())
+    }
+
+    /// Every request the server has received so far, oldest first.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Forget the requests received so far.
+    pub fn clear_requests(&self) {
+        self.state.lock().unwrap().requests.clear();
+    }
+
+    /// Serve `response` for every `method` request to `path` instead of the
+    /// canned response. Later mocks take priority over earlier ones.
+    pub fn mock(&self, method: Method, path: &str, response: MockResponse) {
+        self.add_mock(method, path, response, false);
+    }
+
+    /// Serve `response` for the next `method` request to `path` only. Mocks
+    /// added with `mock_once` are used up in the order they were added.
+    pub fn mock_once(&self, method: Method, path: &str, response: MockResponse) {
+        self.add_mock(method, path, response, true);
+    }
+
+    fn add_mock(&self, method: Method, path: &str, response: MockResponse, once: bool) {
+        self.state.lock().unwrap().mocks.push(Mock {
+            method,
+            path: path.to_owned(),
+            response,
+            once,
+        });
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn handle(
+    base: &str,
+    state: &Mutex<State>,
+    running: &Arc<AtomicBool>,
+    mut request: tiny_http::Request,
+) {
+    let recorded = record(&mut request);
+
+    #[cfg(feature = "websocket")]
+    {
+        let key = recorded.headers.get("sec-websocket-key").cloned();
+        if let (true, Some(key)) = (recorded.path == "/api/v1/streaming", key) {
+            state.lock().unwrap().requests.push(recorded);
+            return serve_websocket(request, key.as_bytes());
+        }
+    }
+
+    let (response, count) = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(recorded.clone());
+        (find_mock(&mut state.mocks, &recorded), state.requests.len())
+    };
+    let mut response = response.unwrap_or_else(|| canned(base, &recorded));
+
+    let has_rate_limit = response
+        .headers
+        .iter()
+        .any(|(name, _)| name.to_lowercase().starts_with("x-ratelimit-"));
+    if !has_rate_limit {
+        let reset = Utc::now() + chrono::Duration::minutes(5);
+        response = response
+            .header("X-RateLimit-Limit", RATE_LIMIT.to_string())
+            .header(
+                "X-RateLimit-Remaining",
+                RATE_LIMIT.saturating_sub(count as u64).to_string(),
+            )
+            .header("X-RateLimit-Reset", reset.to_rfc3339());
+    }
+
+    if response.stall {
+        return serve_stalled(request, response, running.clone());
+    }
+
+    let mut reply = tiny_http::Response::from_data(response.body)
+        .with_status_code(tiny_http::StatusCode(response.status.as_u16()));
+    for (name, value) in response.headers {
+        if let Ok(header) = tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+            reply.add_header(header);
+        }
+    }
+
+    let _ = request.respond(reply);
+}
+
+// Writes the response without a length, so that its body only ends when the
+// connection is closed, and keeps the connection open until the server stops.
+fn serve_stalled(request: tiny_http::Request, response: MockResponse, running: Arc<AtomicBool>) {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nConnection: close\r\n",
+        response.status.as_u16(),
+        response.status.canonical_reason().unwrap_or_default()
+    );
+    for (name, value) in &response.headers {
+        head += &format!("{}: {}\r\n", name, value);
+    }
+    head += "\r\n";
+
+    let mut writer = request.into_writer();
+    let _ = writer
+        .write_all(head.as_bytes())
+        .and_then(|_| writer.write_all(&response.body))
+        .and_then(|_| writer.flush());
+
+    thread::spawn(move || {
+        while running.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(50));
+        }
+        drop(writer);
+    });
+}
+
+// Upgrades the request to the WebSocket endpoint of the streaming API. Each
+// subscription is answered with an `update` event for its stream.
+#[cfg(feature = "websocket")]
+fn serve_websocket(request: tiny_http::Request, key: &[u8]) {
+    use sha1::{Digest, Sha1};
+    use tungstenite::protocol::Role;
+    use tungstenite::{Message, WebSocket};
+
+    let accept = Sha1::new()
+        .chain(key)
+        .chain(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11")
+        .result();
+    let header = tiny_http::Header::from_bytes(
+        &b"Sec-WebSocket-Accept"[..],
+        base64::encode(&accept).as_bytes(),
+    )
+    .unwrap();
+    let response = tiny_http::Response::empty(tiny_http::StatusCode(101)).with_header(header);
+    let stream = request.upgrade("websocket", response);
+
+    thread::spawn(move || {
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+        while let Ok(message) = socket.read_message() {
+            let message: json::Value = match message {
+                Message::Text(text) => json::from_str(&text).unwrap_or_default(),
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            if message["type"] != "subscribe" {
+                continue;
+            }
+
+            let mut stream = vec![message["stream"].clone()];
+            if let Some(param) = message.get("tag").or_else(|| message.get("list")) {
+                stream.push(param.clone());
+            }
+            let event = json!({
+                "stream": stream,
+                "event": "update",
+                "payload": status("4").to_string(),
+            });
+
+            if socket
+                .write_message(Message::Text(event.to_string()))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+}
+
+fn record(request: &mut tiny_http::Request) -> RecordedRequest {
+    // The body of an upgrade request is the rest of the connection.
+    let mut body = Vec::new();
+    if !request.headers().iter().any(|h| h.field.equiv("Upgrade")) {
+        let _ = request.as_reader().read_to_end(&mut body);
+    }
+
+    let mut headers = HeaderMap::new();
+    for header in request.headers() {
+        let name = HeaderName::from_bytes(header.field.as_str().as_str().as_bytes());
+        let value = HeaderValue::from_str(header.value.as_str());
+        if let (Ok(name), Ok(value)) = (name, value) {
+            headers.append(name, value);
+        }
+    }
+
+    let mut url = request.url().splitn(2, '?');
+    let path = url.next().unwrap_or_default().to_owned();
+    let query = url.next().map(String::from);
+
+    RecordedRequest {
+        method: Method::from_bytes(request.method().as_str().as_bytes()).unwrap_or(Method::GET),
+        path,
+        query,
+        headers,
+        body,
+    }
+}
+
+fn find_mock(mocks: &mut Vec<Mock>, request: &RecordedRequest) -> Option<MockResponse> {
+    let matches = |mock: &Mock| mock.method == request.method && mock.path == request.path;
+
+    if let Some(index) = mocks.iter().position(|mock| mock.once && matches(mock)) {
+        return Some(mocks.remove(index).response);
+    }
+
+    mocks
+        .iter()
+        .rev()
+        .find(|mock| matches(mock))
+        .map(|mock| mock.response.clone())
+}
+
+fn canned(base: &str, request: &RecordedRequest) -> MockResponse {
+    let segments: Vec<&str> = request.path.trim_start_matches('/').split('/').collect();
+    let ok = |body: json::Value| MockResponse::json(StatusCode::OK, &body);
+
+    match (&request.method, &segments[..]) {
+        (&Method::POST, ["api", "v1", "apps"]) => {
+            let form: HashMap<String, String> = url::form_urlencoded::parse(&request.body)
+                .into_owned()
+                .collect();
+
+            ok(json!({
+                "id": "1",
+                "name": form.get("client_name"),
+                "website": form.get("website"),
+                "redirect_uri": form.get("redirect_uris"),
+                "client_id": CLIENT_ID,
+                "client_secret": CLIENT_SECRET,
+            }))
+        }
+        (&Method::POST, ["oauth", "token"]) => ok(json!({
+            "access_token": ACCESS_TOKEN,
+            "token_type": "Bearer",
+            "scope": "read write follow push",
+            "created_at": 1_546_300_800,
+        })),
+        (&Method::GET, [".well-known", "oauth-authorization-server"]) => ok(oauth_metadata(base)),
+        (&Method::POST, ["oauth", "revoke"]) => ok(json!({})),
+        (&Method::POST, ["api", "v1", "accounts"]) => ok(json!({
+            "access_token": ACCESS_TOKEN,
+            "token_type": "Bearer",
+            "scope": "read write follow push",
+            "created_at": 1_546_300_800,
+        })),
+        (&Method::GET, ["api", "v1", "apps", "verify_credentials"]) => ok(json!({
+            "name": "mammut_test",
+            "website": null,
+            "vapid_key": "BCk-QqERU0q-CfYZjcuB6lnyyOYfJ2AifKqfeGIm7Z-HiTU5T9eTG5GxVA0_OH5mMlI4UkkDTpaZwozy0TzdZ2M=",
+            "scopes": ["read", "write", "follow", "push"],
+        })),
+        (&Method::GET, ["api", "v1", "instance"]) => ok(instance(base)),
+        (&Method::GET, ["api", "v1", "streaming", ..]) => MockResponse::event_stream(vec![
+            ("update", &*status("4").to_string()),
+            ("notification", &*notification("4").to_string()),
+            ("delete", "1"),
+        ]),
+        (&Method::GET, ["api", "v1", "custom_emojis"]) => ok(json!([emoji()])),
+        (&Method::GET, ["api", "v1", "domain_blocks"]) => ok(json!(["example.com"])),
+        (&Method::GET, ["api", "v1", "reports"]) => ok(json!([report()])),
+        (&Method::POST, ["api", "v1", "reports"]) => ok(report()),
+        (&Method::POST, ["api", "v1", "search"]) => ok(json!({
+            "accounts": [account("1")],
+            "statuses": [status("1")],
+            "hashtags": ["mastodon"],
+        })),
+        (&Method::POST, ["api", "v1", "follows"]) => ok(account("1")),
+        (&Method::POST, ["api", "v1", "media"]) => ok(attachment("1")),
+        (&Method::POST, ["api", "v1", "statuses"]) => {
+            let mut posted = status("4");
+            if let Ok(builder) = request.json::<json::Value>() {
+                posted["content"] = builder["status"].clone();
+            }
+            ok(posted)
+        }
+        (&Method::GET, ["api", "v1", "accounts", "relationships"]) => {
+            ok(json!([relationship("1")]))
+        }
+        (&Method::GET, ["api", "v1", "accounts", "search"]) => ok(json!([account("1")])),
+        (&Method::GET, ["api", "v1", "accounts", "verify_credentials"])
+        | (&Method::PATCH, ["api", "v1", "accounts", "update_credentials"]) => {
+            let mut account = account("1");
+            account["source"] = json!({
+                "privacy": "public",
+                "sensitive": false,
+                "note": "",
+            });
+            ok(account)
+        }
+        (&Method::GET, ["api", "v1", "accounts", id]) => ok(account(id)),
+        (_, ["api", "v1", "accounts", _, "follow"])
+        | (_, ["api", "v1", "accounts", _, "unfollow"])
+        | (_, ["api", "v1", "accounts", _, "block"])
+        | (_, ["api", "v1", "accounts", _, "unblock"])
+        | (_, ["api", "v1", "accounts", _, "mute"])
+        | (_, ["api", "v1", "accounts", _, "unmute"]) => ok(account(segments[3])),
+        (&Method::GET, ["api", "v1", "accounts", _, "statuses"])
+        | (&Method::GET, ["api", "v1", "favourites"])
+        | (&Method::GET, ["api", "v1", "timelines", "home"]) => paginate(base, request, status),
+        (&Method::GET, ["api", "v1", "timelines", "public"])
+        | (&Method::GET, ["api", "v1", "timelines", "tag", _]) => {
+            ok(json!([status("3"), status("2"), status("1")]))
+        }
+        (&Method::GET, ["api", "v1", "accounts", _, "followers"])
+        | (&Method::GET, ["api", "v1", "accounts", _, "following"])
+        | (&Method::GET, ["api", "v1", "statuses", _, "reblogged_by"])
+        | (&Method::GET, ["api", "v1", "statuses", _, "favourited_by"])
+        | (&Method::GET, ["api", "v1", "blocks"])
+        | (&Method::GET, ["api", "v1", "mutes"])
+        | (&Method::GET, ["api", "v1", "follow_requests"]) => paginate(base, request, account),
+        (&Method::GET, ["api", "v1", "notifications"]) => paginate(base, request, notification),
+        (&Method::GET, ["api", "v1", "notifications", id]) => ok(notification(id)),
+        (&Method::GET, ["api", "v1", "statuses", id]) => ok(status(id)),
+        (&Method::GET, ["api", "v1", "statuses", _, "context"]) => ok(json!({
+            "ancestors": [status("1")],
+            "descendants": [status("3")],
+        })),
+        (&Method::GET, ["api", "v1", "statuses", _, "card"]) => ok(card()),
+        (&Method::POST, ["api", "v1", "statuses", id, "reblog"])
+        | (&Method::POST, ["api", "v1", "statuses", id, "unreblog"])
+        | (&Method::POST, ["api", "v1", "statuses", id, "favourite"])
+        | (&Method::POST, ["api", "v1", "statuses", id, "unfavourite"]) => ok(status(id)),
+        (&Method::DELETE, ["api", "v1", "statuses", _])
+        | (&Method::POST, ["api", "v1", "notifications", "clear"])
+        | (&Method::POST, ["api", "v1", "domain_blocks"])
+        | (&Method::DELETE, ["api", "v1", "domain_blocks"])
+        | (&Method::POST, ["api", "v1", "accounts", "follow_requests", _]) => ok(json!({})),
+        _ => MockResponse::json(
+            StatusCode::NOT_FOUND,
+            &json!({ "error": "Record not found" }),
+        ),
+    }
+}
+
+// Serves three items with the ids `3`, `2` and `1`, newest first, filtered by
+// `max_id`, `since_id` and `min_id` and split into pages of `limit` (2 by
+// default) items.
+fn paginate(base: &str, request: &RecordedRequest, item: fn(&str) -> json::Value) -> MockResponse {
+    let param = |name| {
+        request
+            .query_param(name)
+            .and_then(|value| value.parse::<u64>().ok())
+    };
+    let limit = param("limit").unwrap_or(2) as usize;
+    let max_id = param("max_id").unwrap_or(u64::MAX);
+    let min_id = param("min_id").or_else(|| param("since_id")).unwrap_or(0);
+
+    let mut ids: Vec<u64> = (1..=3)
+        .rev()
+        .filter(|&id| id < max_id && id > min_id)
+        .collect();
+    let more = ids.len() > limit;
+    if param("min_id").is_some() {
+        // `min_id` returns the page immediately newer than it.
+        ids = ids.split_off(ids.len().saturating_sub(limit));
+    } else {
+        ids.truncate(limit);
+    }
+
+    let mut response = MockResponse::json(
+        StatusCode::OK,
+        &json::Value::Array(ids.iter().map(|id| item(&id.to_string())).collect()),
+    );
+
+    let link = |rel, query| format!("<{}{}?{}>; rel=\"{}\"", base, request.path, query, rel);
+    let mut links = Vec::new();
+    if let (Some(newest), Some(oldest)) = (ids.first(), ids.last()) {
+        if more || *oldest > 1 {
+            links.push(link("next", format!("max_id={}", oldest)));
+        }
+        links.push(link("prev", format!("min_id={}", newest)));
+    }
+    if !links.is_empty() {
+        response = response.header("Link", links.join(", "));
+    }
+
+    response
+}
+
+fn oauth_metadata(base: &str) -> json::Value {
+    json!({
+        "issuer": format!("{}/", base),
+        "service_documentation": "https://docs.joinmastodon.org/",
+        "authorization_endpoint": format!("{}/oauth/authorize", base),
+        "token_endpoint": format!("{}/oauth/token", base),
+        "app_registration_endpoint": format!("{}/api/v1/apps", base),
+        "revocation_endpoint": format!("{}/oauth/revoke", base),
+        "scopes_supported": [
+            "read", "write", "write:accounts", "write:blocks", "write:bookmarks",
+            "write:conversations", "write:favourites", "write:filters", "write:follows",
+            "write:lists", "write:media", "write:mutes", "write:notifications",
+            "write:reports", "write:statuses", "read:accounts", "read:blocks",
+            "read:bookmarks", "read:favourites", "read:filters", "read:follows",
+            "read:lists", "read:mutes", "read:notifications", "read:search",
+            "read:statuses", "follow", "push", "profile", "admin:read",
+            "admin:read:accounts", "admin:read:reports", "admin:write",
+            "admin:write:accounts", "admin:write:reports",
+        ],
+        "response_types_supported": ["code"],
+        "response_modes_supported": ["query", "fragment", "form_post"],
+        "code_challenge_methods_supported": ["S256"],
+        "grant_types_supported": ["authorization_code", "client_credentials"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post"],
+    })
+}
+
+fn instance(base: &str) -> json::Value {
+    json!({
+        "uri": base.trim_start_matches("http://"),
+        "title": "Mock Mastodon",
+        "description": "A mock instance for testing.",
+        "email": "admin@example.com",
+        "version": "2.9.0",
+        "urls": { "streaming_api": base.replace("http://", "ws://") },
+        "stats": { "user_count": 1, "status_count": 3, "domain_count": 1 },
+        "thumbnail": null,
+        "languages": ["en"],
+        "contact_account": account("1"),
+    })
+}
+
+fn account(id: &str) -> json::Value {
+    json!({
+        "id": id,
+        "username": format!("user{}", id),
+        "acct": format!("user{}", id),
+        "display_name": format!("User {}", id),
+        "locked": false,
+        "created_at": "2019-01-01T00:00:00.000Z",
+        "followers_count": 1,
+        "following_count": 1,
+        "statuses_count": 3,
+        "note": "",
+        "url": format!("https://example.com/@user{}", id),
+        "avatar": "https://example.com/avatar.png",
+        "avatar_static": "https://example.com/avatar.png",
+        "header": "https://example.com/header.png",
+        "header_static": "https://example.com/header.png",
+    })
+}
+
+fn status(id: &str) -> json::Value {
+    json!({
+        "id": id,
+        "uri": format!("https://example.com/users/user1/statuses/{}", id),
+        "url": format!("https://example.com/@user1/{}", id),
+        "account": account("1"),
+        "in_reply_to_id": null,
+        "in_reply_to_account_id": null,
+        "reblog": null,
+        "content": format!("<p>Status {}</p>", id),
+        "created_at": format!("2019-01-0{}T00:00:00.000Z", id.parse::<u8>().unwrap_or(1).min(9)),
+        "emojis": [],
+        "reblogs_count": 0,
+        "favourites_count": 0,
+        "reblogged": false,
+        "favourited": false,
+        "sensitive": false,
+        "spoiler_text": "",
+        "visibility": "public",
+        "media_attachments": [],
+        "mentions": [],
+        "tags": [],
+        "application": { "name": "mammut", "website": null },
+        "language": "en",
+        "pinned": false,
+    })
+}
+
+fn notification(id: &str) -> json::Value {
+    json!({
+        "id": id,
+        "type": "favourite",
+        "created_at": "2019-01-01T00:00:00.000Z",
+        "account": account("2"),
+        "status": status(id),
+    })
+}
+
+fn relationship(id: &str) -> json::Value {
+    json!({
+        "id": id,
+        "following": true,
+        "followed_by": false,
+        "blocking": false,
+        "muting": false,
+        "requested": false,
+        "muting_notifications": false,
+        "domain_blocking": false,
+    })
+}
+
+fn attachment(id: &str) -> json::Value {
+    json!({
+        "id": id,
+        "type": "image",
+        "url": "https://example.com/media/original.png",
+        "remote_url": null,
+        "preview_url": "https://example.com/media/small.png",
+        "text_url": null,
+        "meta": {},
+        "description": null,
+    })
+}
+
+fn emoji() -> json::Value {
+    json!({
+        "shortcode": "mammut",
+        "static_url": "https://example.com/emoji/mammut.png",
+        "url": "https://example.com/emoji/mammut.png",
+    })
+}
+
+fn report() -> json::Value {
+    json!({ "id": "1", "action_taken": "false" })
+}
+
+fn card() -> json::Value {
+    json!({
+        "url": "https://example.com",
+        "title": "Example",
+        "description": "An example card.",
+        "image": null,
+    })
+}
@@ -0,0 +1,404 @@
+//! The HTTP layer between `Mastodon` and an instance.
+//!
+//! Every request `Mastodon` makes is described by a `Request` and handed to a
+//! `Transport`, which sends it and returns the status, headers and body of the
+//! response. `reqwest::Client` is the default transport, but any type
+//! implementing `Transport` can be used instead, such as an in-memory transport
+//! in tests. The streaming API is read through `Transport::send_streaming`, and
+//! the async client sends its requests through an `AsyncTransport`.
+//!
+//! ```
+//! # extern crate mammut;
+//! use std::sync::Mutex;
+//!
+//! use mammut::transport::{Request, Response, Transport};
+//! use mammut::{Data, Mastodon};
+//! use reqwest::header::HeaderMap;
+//! use reqwest::StatusCode;
+//! # extern crate reqwest;
+//!
+//! #[derive(Debug, Default)]
+//! struct InMemory {
+//!     requests: Mutex<Vec<String>>,
+//! }
+//!
+//! impl Transport for InMemory {
+//!     fn send(&self, request: Request) -> mammut::Result<Response> {
+//!         self.requests.lock().unwrap().push(request.url.path().to_owned());
+//!
+//!         Ok(Response {
+//!             status: StatusCode::OK,
+//!             headers: HeaderMap::new(),
+//!             body: b"{}".to_vec(),
+//!         })
+//!     }
+//! }
+//!
+//! # fn main() -> mammut::Result<()> {
+//! let data = Data {
+//!     base: "https://mastodon.social".into(),
+//! #   client_id: "".into(),
+//! #   client_secret: "".into(),
+//! #   redirect: "".into(),
+//! #   token: "access-token".into(),
+//! #   scopes: None,
+//! #   revocation_endpoint: None,
+//!     // ...
+//! };
+//!
+//! let mastodon = Mastodon::from_data_with_transport(data, InMemory::default());
+//! mastodon.clear_notifications()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[cfg(feature = "async")]
+use futures::{Future, Stream};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::{Client, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+#[cfg(feature = "async")]
+use crate::Error;
+use crate::{redact, Result};
+
+/// Sends `Request`s to an instance.
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Send the request, returning the response once its body has been read
+    /// in full. Non-2xx responses should be returned as `Ok`, it's up to the
+    /// caller to decide whether they're an error.
+    fn send(&self, request: Request) -> Result<Response>;
+
+    /// Send the request, returning the response as soon as its headers have
+    /// been received, with a reader for the body as it arrives. Used for the
+    /// streaming API, whose responses don't end.
+    ///
+    /// The default implementation returns the full body read by `send`,
+    /// which is enough for a transport whose responses always end, such as
+    /// an in-memory transport in tests.
+    fn send_streaming(&self, request: Request) -> Result<StreamingResponse> {
+        let response = self.send(request)?;
+
+        Ok(StreamingResponse {
+            status: response.status,
+            headers: response.headers,
+            body: Box::new(io::Cursor::new(response.body)),
+        })
+    }
+}
+
+impl Transport for Client {
+    fn send(&self, request: Request) -> Result<Response> {
+        let mut response = self.send_streaming(request)?;
+
+        let mut body = Vec::new();
+        response.body.read_to_end(&mut body)?;
+
+        Ok(Response {
+            status: response.status,
+            headers: response.headers,
+            body,
+        })
+    }
+
+    fn send_streaming(&self, request: Request) -> Result<StreamingResponse> {
+        let response = self
+            .request(request.method, request.url)
+            .headers(request.headers)
+            .body(request.body)
+            .send()?;
+
+        Ok(StreamingResponse {
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: Box::new(response),
+        })
+    }
+}
+
+/// Sends `Request`s to an instance asynchronously, for the async client.
+/// Implemented for `reqwest::async::Client` by default. Requires the `async`
+/// feature.
+#[cfg(feature = "async")]
+pub trait AsyncTransport: fmt::Debug + Send + Sync {
+    /// Send the request, resolving to the response once its body has been
+    /// read in full, like `Transport::send`.
+    fn send(&self, request: Request) -> Box<dyn Future<Item = Response, Error = Error> + Send>;
+}
+
+#[cfg(feature = "async")]
+impl AsyncTransport for reqwest::r#async::Client {
+    fn send(&self, request: Request) -> Box<dyn Future<Item = Response, Error = Error> + Send> {
+        let response = self
+            .request(request.method, request.url)
+            .headers(request.headers)
+            .body(request.body)
+            .send()
+            .and_then(|response| {
+                let status = response.status();
+                let headers = response.headers().clone();
+
+                response.into_body().concat2().map(move |body| Response {
+                    status,
+                    headers,
+                    body: body.to_vec(),
+                })
+            })
+            .from_err();
+
+        Box::new(response)
+    }
+}
+
+/// A request to be sent by a `Transport`. Its `Debug` output has secrets,
+/// such as the `Authorization` header, redacted.
+#[derive(Clone)]
+pub struct Request {
+    /// The HTTP method of the request.
+    pub method: Method,
+    /// The full url of the request, including the query string.
+    pub url: Url,
+    /// The request's headers.
+    pub headers: HeaderMap,
+    /// The encoded request body, empty if there is none.
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Construct a request with no headers or body.
+    pub fn new(method: Method, url: &str) -> Result<Self> {
+        Ok(Request {
+            method,
+            url: Url::parse(url)?,
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+        })
+    }
+
+    /// Construct a `GET` request.
+    pub fn get(url: &str) -> Result<Self> {
+        Self::new(Method::GET, url)
+    }
+
+    /// Construct a `POST` request.
+    pub fn post(url: &str) -> Result<Self> {
+        Self::new(Method::POST, url)
+    }
+
+    /// Construct a `PATCH` request.
+    pub fn patch(url: &str) -> Result<Self> {
+        Self::new(Method::PATCH, url)
+    }
+
+    /// Construct a `DELETE` request.
+    pub fn delete(url: &str) -> Result<Self> {
+        Self::new(Method::DELETE, url)
+    }
+
+    /// Append `query` serialised as a url encoded form to the query string.
+    pub fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Result<Self> {
+        let encoded = serde_urlencoded::to_string(query)?;
+
+        if !encoded.is_empty() {
+            let query = match self.url.query() {
+                Some(existing) if !existing.is_empty() => format!("{}&{}", existing, encoded),
+                _ => encoded,
+            };
+            self.url.set_query(Some(&query));
+        }
+
+        Ok(self)
+    }
+
+    /// Set the body to `body` serialised as JSON.
+    pub fn json<T: Serialize + ?Sized>(mut self, body: &T) -> Result<Self> {
+        self.body = json::to_vec(body)?;
+        self.headers
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        Ok(self)
+    }
+
+    /// Set the body to `body` serialised as a url encoded form.
+    pub fn form<T: Serialize + ?Sized>(mut self, body: &T) -> Result<Self> {
+        self.body = serde_urlencoded::to_string(body)?.into_bytes();
+        self.headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+        Ok(self)
+    }
+
+    /// Set the body to the encoded multipart form.
+    pub fn multipart(mut self, form: Multipart) -> Self {
+        let (content_type, body) = form.encode();
+        self.body = body;
+        self.headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(&content_type).expect("boundary is always a valid header"),
+        );
+        self
+    }
+}
+
+impl fmt::Debug for Request {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Request")
+            .field("method", &self.method)
+            .field("url", &redact::Link(&self.url))
+            .field("headers", &redact::Headers(&self.headers))
+            .field("body", &format_args!("{} bytes", self.body.len()))
+            .finish()
+    }
+}
+
+/// A response returned from a `Transport`.
+#[derive(Clone)]
+pub struct Response {
+    /// The status code of the response.
+    pub status: StatusCode,
+    /// The response's headers.
+    pub headers: HeaderMap,
+    /// The full response body.
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Deserialise the body from JSON.
+    pub fn json<T: for<'de> Deserialize<'de>>(&self) -> Result<T> {
+        Ok(json::from_slice(&self.body)?)
+    }
+}
+
+impl fmt::Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("status", &self.status)
+            .field("headers", &redact::Headers(&self.headers))
+            .field("body", &format_args!("{} bytes", self.body.len()))
+            .finish()
+    }
+}
+
+/// A response returned from `Transport::send_streaming`, whose body is read
+/// as it arrives.
+pub struct StreamingResponse {
+    /// The status code of the response.
+    pub status: StatusCode,
+    /// The response's headers.
+    pub headers: HeaderMap,
+    /// The response body.
+    pub body: Box<dyn Read + Send>,
+}
+
+impl fmt::Debug for StreamingResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StreamingResponse")
+            .field("status", &self.status)
+            .field("headers", &redact::Headers(&self.headers))
+            .finish()
+    }
+}
+
+/// A `multipart/form-data` body, for uploading files.
+#[derive(Clone, Debug, Default)]
+pub struct Multipart {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug)]
+struct Part {
+    name: String,
+    file_name: Option<String>,
+    content_type: Option<String>,
+    data: Vec<u8>,
+}
+
+impl Multipart {
+    /// Construct an empty form.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a text field to the form.
+    pub fn text<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.parts.push(Part {
+            name: name.into(),
+            file_name: None,
+            content_type: None,
+            data: value.into().into_bytes(),
+        });
+        self
+    }
+
+    /// Read the file at `path` into the form, with its content type guessed
+    /// from the file extension.
+    pub fn file<N: Into<String>, P: AsRef<Path>>(mut self, name: N, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)?;
+        let content_type = mime_guess::from_path(path).first_or_octet_stream();
+
+        self.parts.push(Part {
+            name: name.into(),
+            file_name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            content_type: Some(content_type.to_string()),
+            data,
+        });
+        Ok(self)
+    }
+
+    /// Encode the form, returning the `Content-Type` header value and the
+    /// body.
+    fn encode(&self) -> (String, Vec<u8>) {
+        let boundary = self.boundary();
+        let mut body = Vec::new();
+
+        for part in &self.parts {
+            body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+            body.extend_from_slice(
+                format!(
+                    "Content-Disposition: form-data; name=\"{}\"",
+                    escape(&part.name)
+                )
+                .as_bytes(),
+            );
+            if let Some(ref file_name) = part.file_name {
+                body.extend_from_slice(format!("; filename=\"{}\"", escape(file_name)).as_bytes());
+            }
+            body.extend_from_slice(b"\r\n");
+            if let Some(ref content_type) = part.content_type {
+                body.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+            }
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(&part.data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+        (format!("multipart/form-data; boundary={}", boundary), body)
+    }
+
+    // Picks a boundary that doesn't appear in any of the parts.
+    fn boundary(&self) -> String {
+        (0u64..)
+            .map(|n| format!("mammut-boundary-{:016x}", n))
+            .find(|boundary| {
+                !self.parts.iter().any(|part| {
+                    part.data
+                        .windows(boundary.len())
+                        .any(|window| window == boundary.as_bytes())
+                })
+            })
+            .unwrap()
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,563 @@
+use std::fmt;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::debug;
+use native_tls::{HandshakeError as TlsHandshakeError, TlsConnector};
+use tungstenite::client::AutoStream;
+use tungstenite::handshake::HandshakeError;
+use tungstenite::http;
+use tungstenite::stream::Stream as SocketStream;
+use tungstenite::{Message, WebSocket};
+use url::Url;
+
+use crate::retry::RetryPolicy;
+use crate::streaming::{with_scheme, Event, Stream};
+use crate::{Error, Mastodon, Result};
+
+/// How long the connection may go without receiving anything before it is
+/// considered dead. A ping is sent after half of it.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often a blocked read stops to apply subscriptions made through a
+/// `Subscriptions` handle.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A blocking iterator over the events of many streams, multiplexed over one
+/// WebSocket connection to the streaming API. Created with
+/// `Mastodon::websocket`. Requires the `websocket` feature.
+///
+/// Streams can be subscribed to and unsubscribed from at any time, either on
+/// the `WebSocketStream` itself or from another thread through the handle
+/// returned by `subscriptions`. Each event is returned along with the stream
+/// it came from.
+///
+/// The connection is opened on the first call to `next`. When it drops, or
+/// receives nothing for longer than the heartbeat timeout, the stream
+/// reconnects with the backoff of its reconnect policy and subscribes to every
+/// stream again. If every attempt allowed by the policy fails the error is
+/// returned, and the next call to `next` starts reconnecting again.
+///
+/// The connection is made directly rather than through the transport of the
+/// `Mastodon` it was created from. It sends the user agent and headers, and
+/// keeps to the timeout and connect timeout, set on its `MastodonBuilder`,
+/// but can't be sent through the builder's proxies, so a client with proxies
+/// fails to create one with `Error::WebSocketProxy`. A shared
+/// `reqwest::Client` given to the builder isn't used at all.
+///
+/// A refused subscription is returned as `Error::SubscriptionRefused`. If the
+/// refusal names its stream, the stream is unsubscribed from so it isn't
+/// sent again on reconnect. Mastodon's refusals usually don't, in which case
+/// the error has no stream and the subscriptions are left as they are.
+///
+/// ```
+/// # extern crate mammut;
+/// # fn main() -> mammut::Result<()> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let mastodon = server.mastodon();
+/// use mammut::streaming::{Event, Stream};
+///
+/// let mut events = mastodon.websocket()?;
+/// events.subscribe(Stream::Hashtag("rust".into()))?;
+/// events.subscribe(Stream::List("1".into()))?;
+///
+/// for event in events.by_ref().take(2) {
+///     let (stream, event) = event?;
+///     if let Event::Update(status) = event {
+///         println!("{}: {}", stream, status.content);
+///     }
+/// }
+///
+/// events.unsubscribe(&Stream::Hashtag("rust".into()));
+/// # assert_eq!(events.streams(), &[Stream::List("1".into())]);
+/// # Ok(())
+/// # }
+/// ```
+pub struct WebSocketStream {
+    mastodon: Mastodon,
+    url: Url,
+    streams: Vec<Stream>,
+    socket: Option<WebSocket<AutoStream>>,
+    sender: Sender<Command>,
+    receiver: Receiver<Command>,
+    reconnect: RetryPolicy,
+    heartbeat_timeout: Duration,
+    last_received: Instant,
+    pinged: bool,
+    failures: u32,
+    connections: u64,
+}
+
+/// A handle for subscribing a `WebSocketStream` to streams from another
+/// thread, returned by `WebSocketStream::subscriptions`. The changes are
+/// applied by the thread iterating over the stream, which returns any error
+/// from them.
+///
+/// ```
+/// # extern crate mammut;
+/// # fn main() -> mammut::Result<()> {
+/// # let server = mammut::testing::MockServer::start();
+/// # let mastodon = server.mastodon();
+/// use std::thread;
+/// use mammut::streaming::Stream;
+///
+/// let mut events = mastodon.websocket()?;
+/// let subscriptions = events.subscriptions();
+///
+/// thread::spawn(move || {
+///     subscriptions.subscribe(Stream::PublicLocal).unwrap();
+/// });
+///
+/// let (stream, _event) = events.next().unwrap()?;
+/// assert_eq!(stream, Stream::PublicLocal);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Subscriptions {
+    sender: Sender<Command>,
+}
+
+#[derive(Debug)]
+enum Command {
+    Subscribe(Stream),
+    Unsubscribe(Stream),
+}
+
+/// How the `MastodonBuilder` of a client said to connect, for its WebSocket
+/// connections, which are made outside of its transport.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Connection {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) proxied: bool,
+}
+
+// An event as sent over the WebSocket.
+#[derive(Deserialize)]
+struct Received {
+    #[serde(default)]
+    stream: Vec<String>,
+    event: Option<String>,
+    #[serde(default)]
+    payload: String,
+    error: Option<String>,
+}
+
+impl WebSocketStream {
+    pub(crate) fn new(mastodon: &Mastodon) -> Result<Self> {
+        if mastodon.connection.proxied {
+            return Err(Error::WebSocketProxy);
+        }
+
+        let base = Url::parse(&mastodon.base)?;
+        let (sender, receiver) = mpsc::channel();
+
+        Ok(WebSocketStream {
+            url: websocket_url(&base)?,
+            mastodon: mastodon.clone(),
+            streams: Vec::new(),
+            socket: None,
+            sender,
+            receiver,
+            reconnect: mastodon.session.retry,
+            heartbeat_timeout: HEARTBEAT_TIMEOUT,
+            last_received: Instant::now(),
+            pinged: false,
+            failures: 0,
+            connections: 0,
+        })
+    }
+
+    /// Connect to the streaming API at `url` instead of the instance's base
+    /// url, e.g. the `streaming_api` url from `Mastodon::instance`.
+    /// `http://` and `https://` urls are connected to over `ws://` and
+    /// `wss://`.
+    pub fn streaming_api(mut self, url: &str) -> Result<Self> {
+        self.url = websocket_url(&Url::parse(url)?)?;
+        self.socket = None;
+        Ok(self)
+    }
+
+    /// Set how long the connection may go without receiving anything before
+    /// reconnecting. Defaults to 60 seconds.
+    pub fn heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Set how the stream backs off between reconnects, and how many failed
+    /// attempts in a row it makes before returning the error. Defaults to the
+    /// retry policy of the `Mastodon` the stream was created from.
+    pub fn reconnect_policy(mut self, policy: RetryPolicy) -> Self {
+        self.reconnect = policy;
+        self
+    }
+
+    /// Start receiving the events of `stream`. Does nothing if already
+    /// subscribed to it.
+    ///
+    /// # Errors
+    /// If the stream is the user's own, and `access_token` is not set or
+    /// wasn't granted the `read:statuses` scope, or `read:notifications` for
+    /// `Stream::UserNotification`.
+    pub fn subscribe(&mut self, stream: Stream) -> Result<()> {
+        if let Some(scope) = stream.scope() {
+            self.mastodon.require_scope(scope)?;
+        }
+
+        if !self.streams.contains(&stream) {
+            self.send("subscribe", &stream);
+            self.streams.push(stream);
+        }
+
+        Ok(())
+    }
+
+    /// Stop receiving the events of `stream`. Events for it that were already
+    /// on their way are dropped.
+    pub fn unsubscribe(&mut self, stream: &Stream) {
+        if let Some(index) = self.streams.iter().position(|s| s == stream) {
+            let stream = self.streams.remove(index);
+            self.send("unsubscribe", &stream);
+        }
+    }
+
+    /// The streams subscribed to, in the order they were subscribed.
+    pub fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    /// A handle for subscribing to streams from another thread.
+    pub fn subscriptions(&self) -> Subscriptions {
+        Subscriptions {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// The number of times the stream has connected so far.
+    pub fn connections(&self) -> u64 {
+        self.connections
+    }
+
+    // Send a subscription change if connected. A failed send drops the
+    // connection, and reconnecting subscribes to every stream again.
+    fn send(&mut self, kind: &str, stream: &Stream) {
+        let mut message = json!({ "type": kind, "stream": stream.name() });
+        if let Some((name, value)) = stream.param() {
+            message[name] = json!(value);
+        }
+
+        if let Some(ref mut socket) = self.socket {
+            if let Err(error) = socket.write_message(Message::Text(message.to_string())) {
+                debug!("STREAM DROPPED: {}", error);
+                self.socket = None;
+            }
+        }
+    }
+
+    // Apply the changes made through `Subscriptions` handles.
+    fn apply_commands(&mut self) -> Result<()> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(Command::Subscribe(stream)) => self.subscribe(stream)?,
+                Ok(Command::Unsubscribe(stream)) => self.unsubscribe(&stream),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        let mut request = http::Request::builder().uri(self.url.as_str());
+        for (name, value) in &self.mastodon.session.headers {
+            request = request.header(name.as_str(), value.as_bytes());
+        }
+        let request = request.body(()).map_err(tungstenite::Error::HttpFormat)?;
+
+        let (socket, _) = tungstenite::client(request, self.open()?).map_err(|error| match error {
+            HandshakeError::Failure(error) => Error::from(error),
+            HandshakeError::Interrupted(_) => {
+                io::Error::new(io::ErrorKind::TimedOut, "WebSocket handshake timed out").into()
+            }
+        })?;
+        let tcp = match *socket.get_ref() {
+            SocketStream::Plain(ref tcp) => tcp,
+            SocketStream::Tls(ref tls) => tls.get_ref(),
+        };
+        tcp.set_read_timeout(Some(POLL_INTERVAL))?;
+
+        debug!("STREAM CONNECTED: {}", self.url);
+        self.socket = Some(socket);
+        self.connections += 1;
+        self.last_received = Instant::now();
+        self.pinged = false;
+
+        for stream in self.streams.clone() {
+            self.send("subscribe", &stream);
+        }
+
+        Ok(())
+    }
+
+    // Open the TCP connection, and TLS over it for `wss://` urls, with the
+    // timeouts of the client's builder. Each address the host resolves to is
+    // tried in turn.
+    fn open(&self) -> Result<AutoStream> {
+        let connection = &self.mastodon.connection;
+        let host = self
+            .url
+            .host_str()
+            .ok_or(Error::Url(url::ParseError::EmptyHost))?;
+        let port = self.url.port_or_known_default().unwrap_or(80);
+
+        let mut last_error = None;
+        for address in (host, port).to_socket_addrs()? {
+            let tcp = match connection.connect_timeout {
+                Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+                None => TcpStream::connect(address),
+            };
+
+            match tcp {
+                Ok(tcp) => {
+                    tcp.set_nodelay(true)?;
+                    tcp.set_read_timeout(connection.timeout)?;
+                    tcp.set_write_timeout(connection.timeout)?;
+                    return self.wrap(host, tcp);
+                }
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host has no addresses"))
+            .into())
+    }
+
+    fn wrap(&self, host: &str, tcp: TcpStream) -> Result<AutoStream> {
+        if self.url.scheme() != "wss" {
+            return Ok(SocketStream::Plain(tcp));
+        }
+
+        let connector = TlsConnector::new().map_err(tungstenite::Error::Tls)?;
+        match connector.connect(host, tcp) {
+            Ok(tls) => Ok(SocketStream::Tls(tls)),
+            Err(TlsHandshakeError::Failure(error)) => Err(tungstenite::Error::Tls(error).into()),
+            Err(TlsHandshakeError::WouldBlock(_)) => {
+                Err(io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out").into())
+            }
+        }
+    }
+
+    // Read the next message, returning `None` if nothing arrived before the
+    // read timed out.
+    fn read(&mut self) -> Result<Option<Message>> {
+        let socket = match self.socket {
+            Some(ref mut socket) => socket,
+            None => return Ok(None),
+        };
+
+        match socket.read_message() {
+            Ok(message) => {
+                self.last_received = Instant::now();
+                self.pinged = false;
+                Ok(Some(message))
+            }
+            Err(tungstenite::Error::Io(ref error))
+                if error.kind() == io::ErrorKind::WouldBlock
+                    || error.kind() == io::ErrorKind::TimedOut =>
+            {
+                let idle = self.last_received.elapsed();
+                if idle >= self.heartbeat_timeout {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "missed heartbeat").into());
+                }
+                if idle >= self.heartbeat_timeout / 2 && !self.pinged {
+                    socket.write_message(Message::Ping(Vec::new()))?;
+                    self.pinged = true;
+                }
+                Ok(None)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+impl Iterator for WebSocketStream {
+    type Item = Result<(Stream, Event)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Err(error) = self.apply_commands() {
+                return Some(Err(error));
+            }
+
+            if self.socket.is_none() {
+                if self.failures > 0 {
+                    let delay = self.reconnect.backoff(self.failures);
+                    debug!("STREAM RECONNECTING: after {:?}", delay);
+                    thread::sleep(delay);
+                }
+
+                if let Err(error) = self.connect() {
+                    self.failures += 1;
+                    if is_fatal(&error) || self.failures >= self.reconnect.max_attempts {
+                        self.failures = 0;
+                        return Some(Err(error));
+                    }
+                    continue;
+                }
+            }
+
+            let text = match self.read() {
+                Ok(Some(Message::Text(text))) => text,
+                Ok(Some(Message::Close(_))) => {
+                    debug!("STREAM CLOSED");
+                    self.socket = None;
+                    self.failures += 1;
+                    continue;
+                }
+                Ok(_) => continue,
+                Err(error) => {
+                    debug!("STREAM DROPPED: {}", error);
+                    self.socket = None;
+                    self.failures += 1;
+                    continue;
+                }
+            };
+            self.failures = 0;
+
+            let received: Received = match json::from_str(&text) {
+                Ok(received) => received,
+                Err(error) => return Some(Err(error.into())),
+            };
+
+            let stream = Stream::from_parts(&received.stream);
+
+            if let Some(error) = received.error {
+                if let Some(ref refused) = stream {
+                    self.unsubscribe(refused);
+                }
+                return Some(Err(Error::SubscriptionRefused(stream, error)));
+            }
+
+            let event = match received.event {
+                Some(event) => event,
+                None => continue,
+            };
+
+            // Events for streams that were just unsubscribed from may still
+            // arrive, and are dropped.
+            match stream {
+                Some(ref stream) if self.streams.contains(stream) => {
+                    let event = Event::from_parts(&event, &received.payload);
+                    return Some(event.map(|event| (stream.clone(), event)));
+                }
+                _ => debug!("STREAM IGNORED: {} for {:?}", event, received.stream),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for WebSocketStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WebSocketStream")
+            .field("url", &self.url)
+            .field("streams", &self.streams)
+            .field("reconnect", &self.reconnect)
+            .field("heartbeat_timeout", &self.heartbeat_timeout)
+            .field("connected", &self.socket.is_some())
+            .field("connections", &self.connections)
+            .finish()
+    }
+}
+
+impl Subscriptions {
+    /// Start receiving the events of `stream`.
+    ///
+    /// # Errors
+    /// If the `WebSocketStream` has been dropped.
+    pub fn subscribe(&self, stream: Stream) -> Result<()> {
+        self.send(Command::Subscribe(stream))
+    }
+
+    /// Stop receiving the events of `stream`.
+    ///
+    /// # Errors
+    /// If the `WebSocketStream` has been dropped.
+    pub fn unsubscribe(&self, stream: Stream) -> Result<()> {
+        self.send(Command::Unsubscribe(stream))
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        self.sender.send(command).map_err(|_| {
+            io::Error::new(io::ErrorKind::BrokenPipe, "the stream has been dropped").into()
+        })
+    }
+}
+
+// The url of the WebSocket endpoint of the streaming API at `base`.
+fn websocket_url(base: &Url) -> Result<Url> {
+    let base = match base.scheme() {
+        "http" => with_scheme(base, "ws")?,
+        "https" => with_scheme(base, "wss")?,
+        _ => base.clone(),
+    };
+
+    Ok(base.join("/api/v1/streaming")?)
+}
+
+// Whether reconnecting can't fix the error, e.g. an invalid access token.
+fn is_fatal(error: &Error) -> bool {
+    match *error {
+        Error::WebSocket(tungstenite::Error::Http(status)) => {
+            status.is_client_error() && status.as_u16() != 429
+        }
+        Error::WebSocket(tungstenite::Error::Url(_)) | Error::Url(_) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::MockServer;
+    use crate::MastodonBuilder;
+
+    #[test]
+    fn refuses_a_proxied_client() {
+        let server = MockServer::start();
+        let proxy = reqwest::Proxy::all("http://127.0.0.1:1").unwrap();
+        let mastodon = MastodonBuilder::new(server.data()).proxy(proxy).build().unwrap();
+
+        match mastodon.websocket() {
+            Err(Error::WebSocketProxy) => {}
+            other => panic!("{:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn applies_the_builder_timeouts() {
+        let server = MockServer::start();
+        let timeout = Duration::from_secs(5);
+        let mastodon = MastodonBuilder::new(server.data())
+            .timeout(timeout)
+            .connect_timeout(timeout)
+            .build()
+            .unwrap();
+        let mut events = mastodon.websocket().unwrap();
+
+        match events.open().unwrap() {
+            SocketStream::Plain(tcp) => {
+                assert_eq!(tcp.read_timeout().unwrap(), Some(timeout));
+                assert_eq!(tcp.write_timeout().unwrap(), Some(timeout));
+            }
+            SocketStream::Tls(_) => unreachable!(),
+        }
+
+        events.subscribe(Stream::Public).unwrap();
+        assert_eq!(events.next().unwrap().unwrap().0, Stream::Public);
+    }
+}